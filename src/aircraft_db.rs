@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::io;
+
+/// Static details of one airframe from the local database.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DbRecord {
+    pub registration: Option<String>,
+    pub type_code: Option<String>,
+}
+
+/// Airframes keyed by upper-case ICAO hex address.
+pub type AircraftDb = HashMap<String, DbRecord>;
+
+/// Load a CSV database with a header row naming at least the ICAO address
+/// column (`icao24`, `icao` or `hex`) and optionally `registration`/`reg`
+/// and `typecode`/`type`. The OpenSky aircraft database works as is.
+pub fn load(path: &str) -> io::Result<AircraftDb> {
+    let text = std::fs::read_to_string(path)?;
+    parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn parse(text: &str) -> Result<AircraftDb, String> {
+    let mut lines = text.lines();
+    let header = split_csv_line(lines.next().ok_or("empty aircraft database")?);
+    let column = |names: &[&str]| {
+        header
+            .iter()
+            .position(|h| names.iter().any(|n| h.eq_ignore_ascii_case(n)))
+    };
+
+    let hex_col = column(&["icao24", "icao", "hex"]).ok_or("no icao24/hex column in header")?;
+    let reg_col = column(&["registration", "reg"]);
+    let type_col = column(&["typecode", "type"]);
+
+    let mut db = AircraftDb::new();
+    for line in lines {
+        let fields = split_csv_line(line);
+        let Some(hex) = fields.get(hex_col).filter(|h| !h.is_empty()) else {
+            continue;
+        };
+        let field = |col: Option<usize>| {
+            col.and_then(|c| fields.get(c))
+                .filter(|v| !v.is_empty())
+                .cloned()
+        };
+        db.insert(
+            hex.to_ascii_uppercase(),
+            DbRecord {
+                registration: field(reg_col),
+                type_code: field(type_col),
+            },
+        );
+    }
+    Ok(db)
+}
+
+/// Split one CSV line, honouring double or single quoted fields.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quote = None;
+
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => field.push(c),
+            None if (c == '"' || c == '\'') && field.is_empty() => quote = Some(c),
+            None if c == ',' => fields.push(std::mem::take(&mut field).trim().to_string()),
+            None => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_simple_csv() {
+        let db = parse("icao24,registration,typecode\na1b2c3,N123AB,C172\n").unwrap();
+        assert_eq!(
+            db.get("A1B2C3"),
+            Some(&DbRecord {
+                registration: Some("N123AB".to_string()),
+                type_code: Some("C172".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_quoted_opensky_style() {
+        let text = "\"icao24\",\"registration\",\"manufacturername\",\"typecode\"\n\
+                    \"4ca7b5\",\"EI-DCL\",\"Boeing, Co\",\"B738\"\n";
+        let db = parse(text).unwrap();
+        let rec = db.get("4CA7B5").unwrap();
+        assert_eq!(rec.registration.as_deref(), Some("EI-DCL"));
+        assert_eq!(rec.type_code.as_deref(), Some("B738"));
+    }
+
+    #[test]
+    fn parse_missing_optional_columns() {
+        let db = parse("hex,reg\nabc123,\n").unwrap();
+        let rec = db.get("ABC123").unwrap();
+        assert!(rec.registration.is_none());
+        assert!(rec.type_code.is_none());
+    }
+
+    #[test]
+    fn parse_requires_hex_column() {
+        assert!(parse("registration,typecode\nN1,C172\n").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn parse_skips_rows_without_hex() {
+        let db = parse("icao24,registration\n,N1\nabc123,N2\n").unwrap();
+        assert_eq!(db.len(), 1);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::Fix;
+use tokio::time::{Duration, Instant};
+
+const GRAVITY_MS2: f64 = 9.80665;
+const MAX_BANK_DEG: f64 = 45.0;
+
+/// Pitch and roll derived from ADS-B kinematics, in degrees.
+/// Positive pitch is nose up, positive roll is right wing down.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Attitude {
+    pub pitch_deg: f64,
+    pub roll_deg: f64,
+}
+
+/// ADS-B carries no attitude, so pitch is approximated from the flight path
+/// angle and roll from the bank needed for a coordinated turn at the observed
+/// turn rate.
+#[derive(Default)]
+pub struct AttitudeEstimator {
+    last: Option<(f64, Instant)>,
+}
+
+impl AttitudeEstimator {
+    pub fn update(&mut self, fix: &Fix, now: Instant) -> Attitude {
+        let gs_ms = fix.ground_speed_kt * 0.514444;
+
+        let pitch_deg = match fix.vertical_rate_fpm {
+            Some(vs_fpm) if gs_ms > 1.0 => (vs_fpm * 0.00508).atan2(gs_ms).to_degrees(),
+            _ => 0.0,
+        };
+
+        let roll_deg = match self.last {
+            Some((prev_track, prev_time)) => {
+                let dt = now.duration_since(prev_time);
+                if dt.is_zero() || dt > Duration::from_secs(10) {
+                    0.0
+                } else {
+                    let turn_deg = wrap_degrees(fix.track - prev_track);
+                    let turn_rate = turn_deg.to_radians() / dt.as_secs_f64();
+                    (gs_ms * turn_rate / GRAVITY_MS2)
+                        .atan()
+                        .to_degrees()
+                        .clamp(-MAX_BANK_DEG, MAX_BANK_DEG)
+                }
+            }
+            None => 0.0,
+        };
+
+        self.last = Some((fix.track, now));
+
+        Attitude {
+            pitch_deg,
+            roll_deg,
+        }
+    }
+}
+
+/// Normalise an angle difference into -180..180 degrees.
+fn wrap_degrees(deg: f64) -> f64 {
+    let d = deg.rem_euclid(360.0);
+    if d > 180.0 {
+        d - 360.0
+    } else {
+        d
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fix(track: f64, gs_kt: f64, vs_fpm: Option<f64>) -> Fix {
+        Fix {
+            latitude: 40.0,
+            longitude: -74.0,
+            altitude_ft: 5000.0,
+            track,
+            ground_speed_kt: gs_kt,
+            vertical_rate_fpm: vs_fpm,
+        }
+    }
+
+    #[test]
+    fn level_flight_is_wings_level() {
+        let mut est = AttitudeEstimator::default();
+        let t0 = Instant::now();
+        est.update(&fix(90.0, 250.0, Some(0.0)), t0);
+        let att = est.update(&fix(90.0, 250.0, Some(0.0)), t0 + Duration::from_secs(1));
+        assert_eq!(att, Attitude::default());
+    }
+
+    #[test]
+    fn climb_gives_positive_pitch() {
+        let mut est = AttitudeEstimator::default();
+        let att = est.update(&fix(90.0, 150.0, Some(1500.0)), Instant::now());
+        // 1500 fpm at 150 kt is roughly a 5.6 degree flight path angle
+        assert!((att.pitch_deg - 5.6).abs() < 0.2);
+    }
+
+    #[test]
+    fn descent_gives_negative_pitch() {
+        let mut est = AttitudeEstimator::default();
+        let att = est.update(&fix(90.0, 150.0, Some(-800.0)), Instant::now());
+        assert!(att.pitch_deg < 0.0);
+    }
+
+    #[test]
+    fn right_turn_gives_positive_roll() {
+        let mut est = AttitudeEstimator::default();
+        let t0 = Instant::now();
+        est.update(&fix(90.0, 120.0, None), t0);
+        // Standard rate turn: 3 degrees per second
+        let att = est.update(&fix(93.0, 120.0, None), t0 + Duration::from_secs(1));
+        assert!(att.roll_deg > 15.0 && att.roll_deg < 20.0);
+    }
+
+    #[test]
+    fn left_turn_across_north_gives_negative_roll() {
+        let mut est = AttitudeEstimator::default();
+        let t0 = Instant::now();
+        est.update(&fix(1.0, 120.0, None), t0);
+        let att = est.update(&fix(358.0, 120.0, None), t0 + Duration::from_secs(1));
+        assert!(att.roll_deg < -15.0);
+    }
+
+    #[test]
+    fn roll_is_clamped() {
+        let mut est = AttitudeEstimator::default();
+        let t0 = Instant::now();
+        est.update(&fix(0.0, 450.0, None), t0);
+        let att = est.update(&fix(90.0, 450.0, None), t0 + Duration::from_secs(1));
+        assert_eq!(att.roll_deg, MAX_BANK_DEG);
+    }
+
+    #[test]
+    fn stale_previous_sample_ignored() {
+        let mut est = AttitudeEstimator::default();
+        let t0 = Instant::now();
+        est.update(&fix(0.0, 120.0, None), t0);
+        let att = est.update(&fix(90.0, 120.0, None), t0 + Duration::from_secs(60));
+        assert_eq!(att.roll_deg, 0.0);
+    }
+
+    #[test]
+    fn wrap_degrees_range() {
+        assert_eq!(wrap_degrees(270.0), -90.0);
+        assert_eq!(wrap_degrees(-270.0), 90.0);
+        assert_eq!(wrap_degrees(180.0), 180.0);
+        assert_eq!(wrap_degrees(0.0), 0.0);
+    }
+}
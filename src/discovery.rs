@@ -0,0 +1,187 @@
+use crate::xgps::{self, Destination, Destinations, Discovery, Variant};
+use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::UdpSocket;
+use tokio::time::{self, Duration, Instant};
+
+/// Port ForeFlight broadcasts its presence on.
+pub const PORT: u16 = 63093;
+
+/// ForeFlight announces every few seconds; drop clients silent for longer.
+const EXPIRY: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+struct Announcement {
+    #[serde(rename = "App")]
+    app: String,
+}
+
+/// App name from an announcement such as
+/// `{"App":"ForeFlight","GDL90":{"port":4000}}`. The GDL90 port is where the
+/// EFB wants GDL90, which isn't sent, so it's ignored.
+fn parse_announcement(payload: &[u8]) -> Option<String> {
+    let announcement: Announcement = serde_json::from_slice(payload).ok()?;
+    Some(announcement.app)
+}
+
+/// Add or refresh a discovered client. Returns true when it is new. Clients
+/// that are already configured by hand are left alone. EFBs take XGPS on
+/// `xgps::DEFAULT_PORT` whatever they announce, so that's where it goes.
+fn register(destinations: &mut Vec<Destination>, ip: IpAddr, app: String, now: Instant) -> bool {
+    let addr = SocketAddr::new(ip, xgps::DEFAULT_PORT);
+    if let Some(dest) = destinations.iter_mut().find(|d| d.addr == addr) {
+        if let Some(discovery) = dest.discovery.as_mut() {
+            discovery.app = app;
+            discovery.last_seen = now;
+        }
+        return false;
+    }
+
+    let mut dest = Destination::new(addr, Variant::ForeFlight);
+    dest.discovery = Some(Discovery {
+        app,
+        last_seen: now,
+    });
+    destinations.push(dest);
+    true
+}
+
+/// Remove discovered clients whose announcements have stopped.
+fn expire(destinations: &mut Vec<Destination>, now: Instant) -> Vec<Destination> {
+    let (expired, kept) = destinations.drain(..).partition(|d| {
+        d.discovery
+            .as_ref()
+            .is_some_and(|disc| now.duration_since(disc.last_seen) > EXPIRY)
+    });
+    *destinations = kept;
+    expired
+}
+
+/// Bind the announcement port up front, so another app holding it is
+/// reported at startup instead of ending the bridge later.
+pub async fn bind() -> Result<UdpSocket, String> {
+    UdpSocket::bind(("0.0.0.0", PORT))
+        .await
+        .map_err(|e| format!("cannot listen on UDP port {}: {}", PORT, e))
+}
+
+pub async fn listener(socket: UdpSocket, destinations: Destinations) {
+    println!("Listening for EFB announcements on UDP port {}", PORT);
+
+    let mut buf = [0u8; 1024];
+    let mut interval = time::interval(Duration::from_secs(5));
+
+    loop {
+        tokio::select! {
+            r = socket.recv_from(&mut buf) => {
+                let (len, from) = match r {
+                    Ok(r) => r,
+                    Err(e) => {
+                        eprintln!("Discovery receive error: {}", e);
+                        continue;
+                    }
+                };
+                let Some(app) = parse_announcement(&buf[..len]) else {
+                    continue;
+                };
+                let mut dests = destinations.write().await;
+                if register(&mut dests, from.ip(), app.clone(), Instant::now()) {
+                    println!("Discovered {} at {}, sending XGPS", app, from.ip());
+                }
+            }
+            _ = interval.tick() => {
+                let mut dests = destinations.write().await;
+                for dest in expire(&mut dests, Instant::now()) {
+                    println!("Discovered client at {} stopped announcing, removed", dest.addr.ip());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_foreflight_announcement() {
+        let parsed = parse_announcement(br#"{"App":"ForeFlight","GDL90":{"port":4000}}"#);
+        assert_eq!(parsed.as_deref(), Some("ForeFlight"));
+    }
+
+    #[test]
+    fn parse_announcement_without_gdl90() {
+        let parsed = parse_announcement(br#"{"App":"SomeEFB"}"#);
+        assert_eq!(parsed.as_deref(), Some("SomeEFB"));
+    }
+
+    #[test]
+    fn parse_announcement_rejects_garbage() {
+        assert!(parse_announcement(b"not json").is_none());
+        assert!(parse_announcement(br#"{"GDL90":{"port":4000}}"#).is_none());
+    }
+
+    #[test]
+    fn register_adds_unicast_foreflight_destination() {
+        let mut dests = Vec::new();
+        let now = Instant::now();
+        assert!(register(&mut dests, ip("192.168.1.20"), "ForeFlight".into(), now));
+
+        assert_eq!(dests.len(), 1);
+        assert_eq!(dests[0].addr, "192.168.1.20:49002".parse().unwrap());
+        assert_eq!(dests[0].variant, Variant::ForeFlight);
+        assert_eq!(dests[0].kind(), "unicast");
+        let disc = dests[0].discovery.as_ref().unwrap();
+        assert_eq!(disc.app, "ForeFlight");
+    }
+
+    #[test]
+    fn register_refreshes_existing_client() {
+        let mut dests = Vec::new();
+        let t0 = Instant::now();
+        let t1 = t0 + Duration::from_secs(5);
+        register(&mut dests, ip("192.168.1.20"), "ForeFlight".into(), t0);
+        assert!(!register(&mut dests, ip("192.168.1.20"), "ForeFlight".into(), t1));
+
+        assert_eq!(dests.len(), 1);
+        assert_eq!(dests[0].discovery.as_ref().unwrap().last_seen, t1);
+    }
+
+    #[test]
+    fn register_leaves_configured_destination_alone() {
+        let mut dests = vec![xgps::parse_destination("192.168.1.20").unwrap()];
+        assert!(!register(&mut dests, ip("192.168.1.20"), "ForeFlight".into(), Instant::now()));
+
+        assert_eq!(dests.len(), 1);
+        assert!(dests[0].discovery.is_none());
+        assert_eq!(dests[0].variant, Variant::Standard);
+    }
+
+    #[tokio::test]
+    async fn bind_reports_port_in_use() {
+        // Either this test or something else on the machine holds the port
+        let held = UdpSocket::bind(("0.0.0.0", PORT)).await;
+        let err = bind().await.unwrap_err();
+        assert!(err.starts_with("cannot listen on UDP port 63093"), "{}", err);
+        drop(held);
+    }
+
+    #[test]
+    fn expire_removes_only_silent_discovered_clients() {
+        let t0 = Instant::now();
+        let mut dests = vec![xgps::parse_destination("255.255.255.255").unwrap()];
+        register(&mut dests, ip("192.168.1.20"), "ForeFlight".into(), t0);
+        register(&mut dests, ip("192.168.1.21"), "ForeFlight".into(), t0 + Duration::from_secs(20));
+
+        let expired = expire(&mut dests, t0 + Duration::from_secs(40));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].addr.ip(), ip("192.168.1.20"));
+        assert_eq!(dests.len(), 2);
+        assert!(dests[0].discovery.is_none());
+        assert_eq!(dests[1].addr.ip(), ip("192.168.1.21"));
+    }
+}
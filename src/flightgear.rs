@@ -0,0 +1,124 @@
+use crate::attitude::Attitude;
+use crate::xgps::MainFix;
+use crate::Fix;
+use tokio::net::UdpSocket;
+
+/// Name FlightGear looks up under `$FG_ROOT/Protocol/` for `--generic`.
+pub const PROTOCOL_NAME: &str = "adsb_xgps";
+
+/// Order of the CSV fields and the property each one drives.
+const CHUNKS: &[(&str, &str)] = &[
+    ("latitude", "/position/latitude-deg"),
+    ("longitude", "/position/longitude-deg"),
+    ("altitude", "/position/altitude-ft"),
+    ("heading", "/orientation/heading-deg"),
+    ("speed", "/velocities/airspeed-kt"),
+    ("pitch", "/orientation/pitch-deg"),
+    ("roll", "/orientation/roll-deg"),
+];
+
+/// Generic protocol definition matching `format_line`, to be saved as
+/// `$FG_ROOT/Protocol/adsb_xgps.xml`.
+pub fn protocol_xml() -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0"?>
+<PropertyList>
+ <generic>
+  <input>
+   <line_separator>newline</line_separator>
+   <var_separator>,</var_separator>
+"#,
+    );
+    for (name, node) in CHUNKS {
+        xml.push_str(&format!(
+            "   <chunk>\n    <name>{name}</name>\n    <type>double</type>\n    <node>{node}</node>\n   </chunk>\n"
+        ));
+    }
+    xml.push_str("  </input>\n </generic>\n</PropertyList>\n");
+    xml
+}
+
+/// One generic protocol input line, fields in `CHUNKS` order.
+pub fn format_line(fix: &Fix, attitude: &Attitude) -> String {
+    format!(
+        "{:.6},{:.6},{:.0},{:.1},{:.0},{:.1},{:.1}\n",
+        fix.latitude,
+        fix.longitude,
+        fix.altitude_ft,
+        fix.track,
+        fix.ground_speed_kt,
+        attitude.pitch_deg,
+        attitude.roll_deg,
+    )
+}
+
+/// Send a line for every primary slot fix the broadcaster publishes.
+pub async fn sender(mut fixes: MainFix, host: String, port: u16) {
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .await
+        .expect("Failed to bind UDP socket");
+    let addr = format!("{}:{}", host, port);
+    println!(
+        "Sending FlightGear generic protocol to {} (fgfs --fdm=null --generic=socket,in,1,,{},udp,{})",
+        addr, port, PROTOCOL_NAME
+    );
+
+    while fixes.changed().await.is_ok() {
+        let Some((fix, attitude)) = *fixes.borrow_and_update() else {
+            continue;
+        };
+        let line = format_line(&fix, &attitude);
+        if let Err(e) = socket.send_to(line.as_bytes(), &addr).await {
+            eprintln!("FlightGear UDP send error: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_fix() -> Fix {
+        Fix {
+            latitude: 37.618805,
+            longitude: -122.375416,
+            altitude_ft: 4500.0,
+            track: 284.5,
+            ground_speed_kt: 210.0,
+            vertical_rate_fpm: Some(-700.0),
+        }
+    }
+
+    #[test]
+    fn line_matches_protocol_chunks() {
+        let attitude = Attitude {
+            pitch_deg: -1.9,
+            roll_deg: 12.3,
+        };
+        let line = format_line(&sample_fix(), &attitude);
+        assert!(line.ends_with('\n'));
+
+        let parts: Vec<f64> = line
+            .trim_end()
+            .split(',')
+            .map(|p| p.parse().unwrap())
+            .collect();
+        assert_eq!(parts.len(), CHUNKS.len());
+        assert_eq!(parts, vec![37.618805, -122.375416, 4500.0, 284.5, 210.0, -1.9, 12.3]);
+    }
+
+    #[test]
+    fn protocol_xml_lists_chunks_in_order() {
+        let xml = protocol_xml();
+        assert!(xml.starts_with("<?xml version=\"1.0\"?>\n<PropertyList>\n"));
+        assert!(xml.contains("<line_separator>newline</line_separator>"));
+        assert!(xml.contains("<var_separator>,</var_separator>"));
+        assert_eq!(xml.matches("<chunk>").count(), CHUNKS.len());
+
+        let mut pos = 0;
+        for (_, node) in CHUNKS {
+            let found = xml[pos..].find(node).unwrap();
+            pos += found;
+        }
+    }
+}
@@ -1,12 +1,23 @@
+mod aircraft_db;
+mod attitude;
+mod discovery;
+mod flightgear;
+mod recorder;
+mod tracker;
+mod trail;
 mod web;
+mod xgps;
+mod xplane;
 
+use aircraft_db::AircraftDb;
 use clap::Parser;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::net::TcpStream;
-use tokio::net::UdpSocket;
 use tokio::sync::RwLock;
+use tokio::task::JoinSet;
 use tokio::time::{self, Duration, Instant};
 
 #[derive(Parser)]
@@ -15,13 +26,143 @@ struct Args {
     /// dump1090 server hostname or IP
     server: String,
 
-    /// Flight callsign to track
-    callsign: String,
+    /// Aircraft to track: CALLSIGN, hex:ICAO, reg:REGISTRATION or squawk:CODE.
+    /// Callsigns and registrations may be globs (UAL*) or /regex/.
+    /// near:LAT,LON[,MIN-MAX] follows the closest aircraft to a point.
+    /// This is the target of the primary slot, "main"
+    target: tracker::Target,
+
+    /// Leave the target once this holds and move on to the next --then:
+    /// landed, stale:MINUTES or below:FEET; repeatable or comma separated
+    #[arg(long, value_name = "COND", value_delimiter = ',')]
+    until: Vec<tracker::SwitchCondition>,
+
+    /// Target to follow next, as "TARGET[ until COND[,COND]]"; repeatable,
+    /// followed in order
+    #[arg(long = "then", value_name = "ENTRY")]
+    watch: Vec<tracker::WatchEntry>,
+
+    /// Extra tracking slot as NAME=TARGET, fed to the destinations given as
+    /// NAME=HOST with --dest; repeatable
+    #[arg(long = "slot", value_name = "NAME=TARGET", value_parser = tracker::parse_slot)]
+    slots: Vec<(String, tracker::Target)>,
+
+    /// Distance in nm another aircraft must be closer by before a near: target switches
+    #[arg(long, default_value_t = tracker::DEFAULT_HYSTERESIS_NM)]
+    hysteresis: f64,
+
+    /// Rule for choosing between several matching aircraft: freshest, nearest or lowest
+    #[arg(long = "select", value_name = "RULE", default_value = "freshest")]
+    selection_rule: tracker::SelectionRule,
+
+    /// Receiver position as LAT,LON, used by the nearest rule and as the
+    /// centre of the web map
+    #[arg(long, value_name = "LAT,LON", value_parser = tracker::parse_position)]
+    receiver: Option<(f64, f64)>,
+
+    /// What to send when the tracked aircraft goes stale: stop, hold (repeat the
+    /// last fix) or extrapolate[:SECS] along its track, then stop
+    #[arg(long, value_name = "MODE", default_value = "stop")]
+    on_loss: tracker::LossMode,
+
+    /// Stay on the first aircraft acquired even if it stops matching the target
+    #[arg(long)]
+    lock: bool,
+
+    /// CSV aircraft database (icao24, registration, typecode) for reg: targets
+    #[arg(long, value_name = "FILE")]
+    aircraft_db: Option<String>,
 
-    /// UDP broadcast address for XGPS output
+    /// UDP broadcast address for XGPS output, used when no --dest is given
     #[arg(long, default_value = "255.255.255.255")]
     broadcast: String,
 
+    /// XGPS destination as [SLOT=]HOST[:PORT][@VARIANT] (broadcast, unicast or
+    /// multicast; variant standard or foreflight; fed by the main slot
+    /// unless SLOT is given); repeatable. Older comma-less formats aren't
+    /// supported, as there is no published spec to build them against
+    #[arg(long = "dest", value_name = "[SLOT=]HOST[:PORT][@VARIANT]", value_parser = xgps::parse_destination)]
+    dests: Vec<xgps::Destination>,
+
+    /// Simulator name sent after the XGPS prefix
+    #[arg(long, default_value = xgps::DEFAULT_SIM_NAME, value_parser = xgps::parse_sim_name)]
+    sim_name: String,
+
+    /// Listen for ForeFlight announcements on UDP 63093 and send XGPS to each
+    /// EFB found, always on UDP 49002 (GDL90 isn't sent, so its port is ignored)
+    #[arg(long)]
+    discover: bool,
+
+    /// TTL for XGPS datagrams sent to multicast destinations
+    #[arg(long, default_value_t = 1)]
+    multicast_ttl: u32,
+
+    /// X-Plane host to drive the user aircraft with VEHX packets from the main slot
+    #[arg(long)]
+    xplane: Option<String>,
+
+    /// UDP port of the X-Plane host
+    #[arg(long, default_value_t = 49000)]
+    xplane_port: u16,
+
+    /// FlightGear host to slave to the main slot's aircraft over the generic protocol
+    #[arg(long)]
+    flightgear: Option<String>,
+
+    /// UDP port of the FlightGear generic socket
+    #[arg(long, default_value_t = 5500)]
+    flightgear_port: u16,
+
+    /// Write the matching FlightGear protocol XML to this file and continue
+    #[arg(long, value_name = "FILE")]
+    flightgear_protocol: Option<String>,
+
+    /// Record what each slot sends over XGPS to files in this directory,
+    /// starting new files whenever its target or aircraft changes
+    #[arg(long, value_name = "DIR")]
+    record: Option<PathBuf>,
+
+    /// Recording formats: gpx, kml or csv; repeatable or comma separated
+    #[arg(long, value_name = "FORMAT", value_delimiter = ',', default_value = "gpx")]
+    record_format: Vec<recorder::RecordFormat>,
+
+    /// Web interface address as HOST:PORT, [IPV6]:PORT or PORT; localhost:PORT
+    /// keeps it to this machine; repeatable
+    #[arg(long = "web-bind", value_name = "ADDR", default_value = web::DEFAULT_BIND, value_parser = web::parse_bind)]
+    web_binds: Vec<web::WebBind>,
+
+    /// Run without the web interface
+    #[arg(long, conflicts_with = "web_binds")]
+    no_web: bool,
+
+    /// Ask for this HTTP basic login before changes over the web, as USER:HASH
+    /// with an argon2 PHC hash, e.g. from `echo -n PASSWORD | argon2 SALT -id -e`
+    #[arg(long, value_name = "USER:HASH", value_parser = web::parse_login)]
+    web_login: Option<web::Login>,
+
+    /// Accept this bearer token for changes over the web, alone or alongside
+    /// --web-login
+    #[arg(long, value_name = "TOKEN", value_parser = web::parse_token)]
+    web_token: Option<String>,
+
+    /// Ask for the login or token on read-only pages and feeds as well
+    #[arg(long)]
+    web_private: bool,
+
+    /// Serve the web interface over HTTPS with this PEM certificate chain,
+    /// reloaded whenever it or the key changes
+    #[arg(long, value_name = "FILE", requires = "web_key")]
+    web_cert: Option<PathBuf>,
+
+    /// PEM private key for --web-cert
+    #[arg(long, value_name = "FILE", requires = "web_cert")]
+    web_key: Option<PathBuf>,
+
+    /// Create a self-signed certificate and key at --web-cert and --web-key
+    /// if neither exists yet
+    #[arg(long, requires = "web_cert")]
+    web_self_signed: bool,
+
     /// Print all tracked aircraft every second
     #[arg(long)]
     debug: bool,
@@ -29,103 +170,203 @@ struct Args {
 
 pub struct Aircraft {
     pub callsign: Option<String>,
+    pub registration: Option<String>,
+    pub type_code: Option<String>,
+    pub squawk: Option<String>,
     pub latitude: Option<f64>,
     pub longitude: Option<f64>,
     pub altitude_ft: Option<f64>,
     pub ground_speed_kt: Option<f64>,
     pub track: Option<f64>,
+    pub vertical_rate_fpm: Option<f64>,
+    pub on_ground: Option<bool>,
+    pub trail: trail::Trail,
+    pub updated: FieldTimes,
+    /// Messages received by SBS transmission type, MSG,1 first
+    pub messages: [u64; 8],
     pub last_updated: Instant,
 }
 
+/// When each field of an `Aircraft` was last reported.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FieldTimes {
+    pub callsign: Option<Instant>,
+    pub squawk: Option<Instant>,
+    /// Latitude or longitude
+    pub position: Option<Instant>,
+    pub altitude_ft: Option<Instant>,
+    pub ground_speed_kt: Option<Instant>,
+    pub track: Option<Instant>,
+    pub vertical_rate_fpm: Option<Instant>,
+    pub on_ground: Option<Instant>,
+}
+
 pub type AircraftMap = Arc<RwLock<HashMap<String, Aircraft>>>;
-pub type TrackedCallsign = Arc<RwLock<String>>;
 
-fn parse_sbs_line(line: &str, aircraft_map: &mut HashMap<String, Aircraft>) {
+/// Complete position and velocity of the tracked aircraft, as handed to the
+/// outputs.
+#[derive(Clone, Copy, Debug)]
+pub struct Fix {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude_ft: f64,
+    pub track: f64,
+    pub ground_speed_kt: f64,
+    pub vertical_rate_fpm: Option<f64>,
+}
+
+/// Parse one SBS line into the map, returning the hex ident it updated.
+fn parse_sbs_line<'a>(line: &'a str, aircraft_map: &mut HashMap<String, Aircraft>) -> Option<&'a str> {
     let fields: Vec<&str> = line.split(',').collect();
     if fields.len() < 22 {
-        return;
+        return None;
     }
     if fields[0] != "MSG" {
-        return;
+        return None;
     }
 
-    let msg_type: u8 = match fields[1].trim().parse() {
-        Ok(t) => t,
-        Err(_) => return,
-    };
+    let msg_type: u8 = fields[1].trim().parse().ok()?;
 
     let hex_ident = fields[4].trim();
     if hex_ident.is_empty() {
-        return;
+        return None;
     }
 
     let aircraft = aircraft_map
         .entry(hex_ident.to_string())
         .or_insert_with(|| Aircraft {
             callsign: None,
+            registration: None,
+            type_code: None,
+            squawk: None,
             latitude: None,
             longitude: None,
             altitude_ft: None,
             ground_speed_kt: None,
             track: None,
+            vertical_rate_fpm: None,
+            on_ground: None,
+            trail: trail::Trail::default(),
+            updated: FieldTimes::default(),
+            messages: [0; 8],
             last_updated: Instant::now(),
         });
 
+    let now = Instant::now();
+    if (1..=8).contains(&msg_type) {
+        aircraft.messages[usize::from(msg_type) - 1] += 1;
+    }
     match msg_type {
         1 => {
             let cs = fields[10].trim();
             if !cs.is_empty() {
                 aircraft.callsign = Some(cs.to_string());
+                aircraft.updated.callsign = Some(now);
             }
         }
         2 => {
             if let Ok(v) = fields[11].trim().parse() {
                 aircraft.altitude_ft = Some(v);
+                aircraft.updated.altitude_ft = Some(now);
             }
             if let Ok(v) = fields[12].trim().parse() {
                 aircraft.ground_speed_kt = Some(v);
+                aircraft.updated.ground_speed_kt = Some(now);
             }
             if let Ok(v) = fields[13].trim().parse() {
                 aircraft.track = Some(v);
+                aircraft.updated.track = Some(now);
             }
             if let Ok(v) = fields[14].trim().parse() {
                 aircraft.latitude = Some(v);
+                aircraft.updated.position = Some(now);
             }
             if let Ok(v) = fields[15].trim().parse() {
                 aircraft.longitude = Some(v);
+                aircraft.updated.position = Some(now);
             }
         }
         3 => {
             if let Ok(v) = fields[11].trim().parse() {
                 aircraft.altitude_ft = Some(v);
+                aircraft.updated.altitude_ft = Some(now);
             }
             if let Ok(v) = fields[14].trim().parse() {
                 aircraft.latitude = Some(v);
+                aircraft.updated.position = Some(now);
             }
             if let Ok(v) = fields[15].trim().parse() {
                 aircraft.longitude = Some(v);
+                aircraft.updated.position = Some(now);
             }
         }
         4 => {
             if let Ok(v) = fields[12].trim().parse() {
                 aircraft.ground_speed_kt = Some(v);
+                aircraft.updated.ground_speed_kt = Some(now);
             }
             if let Ok(v) = fields[13].trim().parse() {
                 aircraft.track = Some(v);
+                aircraft.updated.track = Some(now);
+            }
+            if let Ok(v) = fields[16].trim().parse() {
+                aircraft.vertical_rate_fpm = Some(v);
+                aircraft.updated.vertical_rate_fpm = Some(now);
             }
         }
         5 | 7 => {
             if let Ok(v) = fields[11].trim().parse() {
                 aircraft.altitude_ft = Some(v);
+                aircraft.updated.altitude_ft = Some(now);
+            }
+        }
+        6 => {
+            let sq = fields[17].trim();
+            if !sq.is_empty() {
+                aircraft.squawk = Some(sq.to_string());
+                aircraft.updated.squawk = Some(now);
             }
         }
         _ => {}
     }
 
-    aircraft.last_updated = Instant::now();
+    // Field 22 is the ground flag, -1 when set
+    let on_ground = match fields[21].trim() {
+        "-1" | "1" => Some(true),
+        "0" => Some(false),
+        _ => None,
+    };
+    if on_ground.is_some() {
+        aircraft.on_ground = on_ground;
+        aircraft.updated.on_ground = Some(now);
+    }
+
+    if let (2 | 3, Ok(lat), Ok(lon)) = (msg_type, fields[14].trim().parse(), fields[15].trim().parse()) {
+        aircraft.trail.push(trail::TrailPoint {
+            latitude: lat,
+            longitude: lon,
+            altitude_ft: aircraft.altitude_ft,
+            ground_speed_kt: aircraft.ground_speed_kt,
+            at: now,
+        });
+    }
+
+    aircraft.last_updated = now;
+    Some(hex_ident)
 }
 
-async fn sbs_reader(server: String, aircraft_map: AircraftMap) {
+/// Fill in registration and type from the database, once per airframe.
+fn annotate_aircraft(hex: &str, aircraft: &mut Aircraft, db: &AircraftDb) {
+    if aircraft.registration.is_some() || aircraft.type_code.is_some() {
+        return;
+    }
+    if let Some(record) = db.get(&hex.to_ascii_uppercase()) {
+        aircraft.registration = record.registration.clone();
+        aircraft.type_code = record.type_code.clone();
+    }
+}
+
+async fn sbs_reader(server: String, aircraft_map: AircraftMap, aircraft_db: Arc<AircraftDb>) {
     let addr = format!("{}:30003", server);
 
     loop {
@@ -146,7 +387,11 @@ async fn sbs_reader(server: String, aircraft_map: AircraftMap) {
 
         while let Ok(Some(line)) = lines.next_line().await {
             let mut map = aircraft_map.write().await;
-            parse_sbs_line(&line, &mut map);
+            if let Some(hex) = parse_sbs_line(&line, &mut map) {
+                if let Some(aircraft) = map.get_mut(hex) {
+                    annotate_aircraft(hex, aircraft, &aircraft_db);
+                }
+            }
         }
 
         eprintln!("Connection to {} closed. Reconnecting in 1s...", addr);
@@ -154,57 +399,33 @@ async fn sbs_reader(server: String, aircraft_map: AircraftMap) {
     }
 }
 
-async fn xgps_broadcaster(callsign: TrackedCallsign, aircraft_map: AircraftMap, broadcast: String) {
-    let socket = UdpSocket::bind("0.0.0.0:0")
-        .await
-        .expect("Failed to bind UDP socket");
-    socket
-        .set_broadcast(true)
-        .expect("Failed to enable broadcast");
+/// Aircraft not heard for this long are dropped, trail and all, unless a
+/// slot is following them; a full trail covers the same ten minutes.
+const FORGET_AFTER: Duration = Duration::from_secs(600);
 
-    let mut interval = time::interval(Duration::from_secs(1));
+/// Drop aircraft silent for longer than `FORGET_AFTER`, except those in
+/// `keep`. Returns how many were dropped.
+fn forget_silent(map: &mut HashMap<String, Aircraft>, keep: &[String], now: Instant) -> usize {
+    let before = map.len();
+    map.retain(|hex, a| keep.contains(hex) || now.saturating_duration_since(a.last_updated) <= FORGET_AFTER);
+    before - map.len()
+}
+
+/// Keep the map to aircraft still around, so a long-running bridge doesn't
+/// hold every airframe it has ever heard.
+async fn aircraft_pruner(aircraft_map: AircraftMap, slots: tracker::Slots) {
+    let mut interval = time::interval(Duration::from_secs(10));
 
     loop {
         interval.tick().await;
 
-        let callsign = callsign.read().await.clone();
-        let map = aircraft_map.read().await;
-        let found = map.values().find(|a| {
-            a.callsign
-                .as_ref()
-                .is_some_and(|cs| cs.eq_ignore_ascii_case(&callsign))
-        });
-
-        let Some(aircraft) = found else {
-            continue;
-        };
-
-        if aircraft.last_updated.elapsed() > Duration::from_secs(5) {
-            continue;
-        }
-
-        let (Some(lon), Some(lat), Some(alt_ft), Some(track), Some(gs_kt)) = (
-            aircraft.longitude,
-            aircraft.latitude,
-            aircraft.altitude_ft,
-            aircraft.track,
-            aircraft.ground_speed_kt,
-        ) else {
-            continue;
-        };
-
-        let alt_m = alt_ft * 0.3048;
-        let gs_ms = gs_kt * 0.514444;
-
-        let msg = format!("XGPSadsb_xgps,{lon},{lat},{alt_m:.1},{track:.2},{gs_ms:.1}");
-        if let Err(e) = socket
-            .send_to(msg.as_bytes(), format!("{}:49002", broadcast))
-            .await
-        {
-            eprintln!("UDP send error: {}", e);
-        } else {
-            println!("{}", msg);
+        let mut map = aircraft_map.write().await;
+        let mut followed = Vec::new();
+        for slot in slots.iter() {
+            let t = slot.tracker.read().await;
+            followed.extend(t.current.iter().chain(&t.locked).cloned());
         }
+        forget_silent(&mut map, &followed, Instant::now());
     }
 }
 
@@ -237,38 +458,284 @@ async fn debug_printer(aircraft_map: AircraftMap) {
     }
 }
 
+/// Name of the one task the bridge can run without.
+const WEB_TASK: &str = "Web server";
+
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
+
+    if let Some(path) = &args.flightgear_protocol {
+        if let Err(e) = std::fs::write(path, flightgear::protocol_xml()) {
+            eprintln!("Failed to write FlightGear protocol to {}: {}", path, e);
+            std::process::exit(1);
+        }
+        println!(
+            "Wrote FlightGear protocol to {}; install it as $FG_ROOT/Protocol/{}.xml",
+            path,
+            flightgear::PROTOCOL_NAME
+        );
+    }
+
     let aircraft_map: AircraftMap = Arc::new(RwLock::new(HashMap::new()));
-    let tracked_callsign: TrackedCallsign = Arc::new(RwLock::new(args.callsign));
+    if args.selection_rule == tracker::SelectionRule::Nearest && args.receiver.is_none() {
+        eprintln!("--select nearest needs --receiver LAT,LON");
+        std::process::exit(1);
+    }
 
-    let reader_handle = tokio::spawn(sbs_reader(args.server, aircraft_map.clone()));
-    let broadcaster_handle =
-        tokio::spawn(xgps_broadcaster(tracked_callsign.clone(), aircraft_map.clone(), args.broadcast));
-    let web_handle = tokio::spawn(web::run(aircraft_map.clone(), tracked_callsign.clone()));
+    if !args.until.is_empty() && args.watch.is_empty() {
+        eprintln!("--until has nothing to switch to without --then");
+        std::process::exit(1);
+    }
 
-    #[allow(clippy::collapsible_if)]
-    if args.debug {
-        let debug_handle = tokio::spawn(debug_printer(aircraft_map));
-        tokio::select! {
-            r = reader_handle => { if let Err(e) = r { eprintln!("SBS reader task failed: {}", e); } }
-            r = broadcaster_handle => { if let Err(e) = r { eprintln!("XGPS broadcaster task failed: {}", e); } }
-            r = debug_handle => { if let Err(e) = r { eprintln!("Debug printer task failed: {}", e); } }
-            r = web_handle => { if let Err(e) = r { eprintln!("Web server task failed: {}", e); } }
+    // The watch list belongs to the primary slot, the first one built
+    let mut primary_watch = Some((args.until, args.watch));
+    let mut slots = Vec::new();
+    for (name, target) in std::iter::once((tracker::PRIMARY_SLOT.to_string(), args.target)).chain(args.slots) {
+        if slots.iter().any(|s: &tracker::Slot| s.name == name) {
+            eprintln!("Slot '{}' is defined more than once", name);
+            std::process::exit(1);
+        }
+        let mut initial_tracker = tracker::Tracker::new(target);
+        initial_tracker.lock = args.lock;
+        initial_tracker.rule = args.selection_rule;
+        initial_tracker.receiver = args.receiver;
+        initial_tracker.hysteresis_nm = args.hysteresis;
+        initial_tracker.on_loss = args.on_loss;
+        if let Some((until, watch)) = primary_watch.take() {
+            initial_tracker.until = until;
+            initial_tracker.watch = watch.into();
+        }
+        slots.push(tracker::Slot {
+            name,
+            tracker: Arc::new(RwLock::new(initial_tracker)),
+        });
+    }
+    let slots: tracker::Slots = Arc::new(slots);
+    let (main_fix, main_fixes) = tokio::sync::watch::channel(None);
+
+    let aircraft_db = match &args.aircraft_db {
+        Some(path) => match aircraft_db::load(path) {
+            Ok(db) => {
+                println!("Loaded {} aircraft from {}", db.len(), path);
+                db
+            }
+            Err(e) => {
+                eprintln!("Failed to load aircraft database {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => AircraftDb::new(),
+    };
+
+    let mut dests = args.dests;
+    if dests.is_empty() {
+        match xgps::parse_destination(&args.broadcast) {
+            Ok(dest) => dests.push(dest),
+            Err(e) => {
+                eprintln!("Invalid broadcast address {}: {}", args.broadcast, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    for dest in &dests {
+        if tracker::slot_index(&slots, dest.slot.as_deref()).is_none() {
+            eprintln!(
+                "Destination {} names unknown slot '{}'",
+                dest.addr,
+                dest.slot.as_deref().unwrap_or_default()
+            );
+            std::process::exit(1);
+        }
+    }
+    let destinations: xgps::Destinations = Arc::new(RwLock::new(dests));
+
+    let recorder = args.record.as_ref().map(|dir| {
+        match recorder::Recorder::new(dir.clone(), args.record_format.clone()) {
+            Ok(recorder) => {
+                println!("Recording to {}", dir.display());
+                recorder
+            }
+            Err(e) => {
+                eprintln!("Failed to create recording directory {}: {}", dir.display(), e);
+                std::process::exit(1);
+            }
+        }
+    });
+
+    let web_auth = web::Auth::new(args.web_login, args.web_token, args.web_private);
+    if args.web_private && web_auth.is_none() {
+        eprintln!("--web-private needs --web-login or --web-token");
+        std::process::exit(1);
+    }
+
+    let web_listeners = if args.no_web {
+        None
+    } else {
+        match web::bind(&args.web_binds).await {
+            Ok(listeners) => Some(listeners),
+            // The web interface is optional, so the bridge runs without it
+            Err(e) => {
+                eprintln!(
+                    "Web server {}; running without it (pick another address with --web-bind, or use --no-web)",
+                    e
+                );
+                None
+            }
+        }
+    };
+
+    let web_tls = match (&args.web_cert, &args.web_key) {
+        (Some(cert), Some(key)) if web_listeners.is_some() => {
+            let files = web::TlsFiles {
+                cert: cert.clone(),
+                key: key.clone(),
+            };
+            if args.web_self_signed {
+                match files.create_self_signed(web::self_signed_names(&args.web_binds)) {
+                    Ok(true) => println!(
+                        "Wrote a self-signed certificate to {} and its key to {}",
+                        cert.display(),
+                        key.display()
+                    ),
+                    Ok(false) => {}
+                    Err(e) => {
+                        eprintln!("Web server {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            match files.load().await {
+                Ok(tls) => Some(tls),
+                Err(e) => {
+                    eprintln!("Web server {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let discovery_socket = if args.discover {
+        match discovery::bind().await {
+            Ok(socket) => Some(socket),
+            Err(e) => {
+                eprintln!(
+                    "EFB discovery {}; another EFB helper may hold it, so stop it or drop --discover",
+                    e
+                );
+                std::process::exit(1);
+            }
         }
     } else {
-        tokio::select! {
-            r = reader_handle => { if let Err(e) = r { eprintln!("SBS reader task failed: {}", e); } }
-            r = broadcaster_handle => { if let Err(e) = r { eprintln!("XGPS broadcaster task failed: {}", e); } }
-            r = web_handle => { if let Err(e) = r { eprintln!("Web server task failed: {}", e); } }
+        None
+    };
+
+    let mut tasks = JoinSet::new();
+    let mut names = HashMap::new();
+    names.insert(
+        tasks
+            .spawn(sbs_reader(args.server, aircraft_map.clone(), Arc::new(aircraft_db)))
+            .id(),
+        "SBS reader",
+    );
+    names.insert(
+        tasks.spawn(aircraft_pruner(aircraft_map.clone(), slots.clone())).id(),
+        "Aircraft pruner",
+    );
+    names.insert(
+        tasks
+            .spawn(xgps::broadcaster(
+                slots.clone(),
+                aircraft_map.clone(),
+                destinations.clone(),
+                args.sim_name,
+                args.multicast_ttl,
+                recorder,
+                main_fix,
+            ))
+            .id(),
+        "XGPS broadcaster",
+    );
+    if let Some(listeners) = web_listeners {
+        names.insert(
+            tasks
+                .spawn(web::run(
+                    listeners,
+                    aircraft_map.clone(),
+                    slots.clone(),
+                    destinations.clone(),
+                    web::Options {
+                        receiver: args.receiver,
+                        recordings: args.record,
+                        auth: web_auth,
+                        tls: web_tls,
+                        ticks: main_fixes.clone(),
+                    },
+                ))
+                .id(),
+            WEB_TASK,
+        );
+    }
+    if let Some(socket) = discovery_socket {
+        names.insert(
+            tasks.spawn(discovery::listener(socket, destinations.clone())).id(),
+            "Discovery listener",
+        );
+    }
+    if let Some(host) = args.xplane {
+        names.insert(
+            tasks
+                .spawn(xplane::sender(
+                    main_fixes.clone(),
+                    host,
+                    args.xplane_port,
+                ))
+                .id(),
+            "X-Plane sender",
+        );
+    }
+    if let Some(host) = args.flightgear {
+        names.insert(
+            tasks
+                .spawn(flightgear::sender(
+                    main_fixes.clone(),
+                    host,
+                    args.flightgear_port,
+                ))
+                .id(),
+            "FlightGear sender",
+        );
+    }
+    if args.debug {
+        names.insert(tasks.spawn(debug_printer(aircraft_map)).id(), "Debug printer");
+    }
+
+    // All tasks run forever. The web server may stop and leave the rest
+    // running; any other task ending is fatal.
+    while let Some(result) = tasks.join_next_with_id().await {
+        let name = match result {
+            Ok((id, ())) => {
+                eprintln!("{} task stopped", names[&id]);
+                names[&id]
+            }
+            Err(e) => {
+                eprintln!("{} task failed: {}", names[&e.id()], e);
+                names[&e.id()]
+            }
+        };
+        if name != WEB_TASK {
+            std::process::exit(1);
         }
+        eprintln!("Carrying on without the web interface");
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::aircraft_db::DbRecord;
+    use crate::tracker::Tracker;
     use std::collections::HashMap;
 
     fn empty_map() -> HashMap<String, Aircraft> {
@@ -364,6 +831,18 @@ mod tests {
         assert_eq!(a.track, Some(179.0));
     }
 
+    #[test]
+    fn msg4_sets_vertical_rate() {
+        let mut map = empty_map();
+        parse_sbs_line(
+            &sbs_line(4, "ABC123", &[(12, "420"), (13, "179"), (16, "-1536")]),
+            &mut map,
+        );
+
+        let a = map.get("ABC123").unwrap();
+        assert_eq!(a.vertical_rate_fpm, Some(-1536.0));
+    }
+
     #[test]
     fn msg4_does_not_set_position() {
         let mut map = empty_map();
@@ -536,40 +1015,53 @@ mod tests {
         assert_eq!(a.latitude, Some(50.0));
     }
 
-    // --- XGPS format ---
-
-    #[test]
-    fn xgps_format_string() {
-        let lon: f64 = -80.11;
-        let lat: f64 = 34.55;
-        let alt_ft: f64 = 3937.0;
-        let track: f64 = 359.05;
-        let gs_kt: f64 = 108.089;
+    // --- Tracked fix ---
 
-        let alt_m = alt_ft * 0.3048;
-        let gs_ms = gs_kt * 0.514444;
+    fn tracked_fix(map: &HashMap<String, Aircraft>, target: &str) -> Option<Fix> {
+        Tracker::new(target.parse().unwrap()).update(map)
+    }
 
-        let msg = format!("XGPSadsb_xgps,{lon},{lat},{alt_m:.1},{track:.2},{gs_ms:.1}");
-        assert!(msg.starts_with("XGPSadsb_xgps,"));
+    fn full_aircraft_map(callsign: &str) -> HashMap<String, Aircraft> {
+        let mut map = empty_map();
+        parse_sbs_line(&sbs_line(1, "AABBCC", &[(10, callsign)]), &mut map);
+        parse_sbs_line(
+            &sbs_line(3, "AABBCC", &[(11, "35000"), (14, "40.0"), (15, "-74.0")]),
+            &mut map,
+        );
+        parse_sbs_line(
+            &sbs_line(4, "AABBCC", &[(12, "450"), (13, "270")]),
+            &mut map,
+        );
+        map
+    }
 
-        let parts: Vec<&str> = msg
-            .strip_prefix("XGPSadsb_xgps,")
-            .unwrap()
-            .split(',')
-            .collect();
-        assert_eq!(parts.len(), 5);
+    #[test]
+    fn tracked_fix_complete_aircraft() {
+        let map = full_aircraft_map("UAL123");
+        let fix = tracked_fix(&map, "ual123").unwrap();
+        assert_eq!(fix.latitude, 40.0);
+        assert_eq!(fix.longitude, -74.0);
+        assert_eq!(fix.altitude_ft, 35000.0);
+        assert_eq!(fix.track, 270.0);
+        assert_eq!(fix.ground_speed_kt, 450.0);
+        assert!(fix.vertical_rate_fpm.is_none());
+    }
 
-        let parsed_lon: f64 = parts[0].parse().unwrap();
-        let parsed_lat: f64 = parts[1].parse().unwrap();
-        let parsed_alt: f64 = parts[2].parse().unwrap();
-        let parsed_track: f64 = parts[3].parse().unwrap();
-        let parsed_gs: f64 = parts[4].parse().unwrap();
+    #[test]
+    fn tracked_fix_unknown_callsign() {
+        let map = full_aircraft_map("UAL123");
+        assert!(tracked_fix(&map, "DAL456").is_none());
+    }
 
-        assert!((parsed_lon - (-80.11)).abs() < 0.01);
-        assert!((parsed_lat - 34.55).abs() < 0.01);
-        assert!((parsed_alt - 1200.1).abs() < 0.2);
-        assert!((parsed_track - 359.05).abs() < 0.01);
-        assert!((parsed_gs - 55.6).abs() < 0.2);
+    #[test]
+    fn tracked_fix_requires_velocity() {
+        let mut map = empty_map();
+        parse_sbs_line(&sbs_line(1, "AABBCC", &[(10, "UAL123")]), &mut map);
+        parse_sbs_line(
+            &sbs_line(3, "AABBCC", &[(11, "35000"), (14, "40.0"), (15, "-74.0")]),
+            &mut map,
+        );
+        assert!(tracked_fix(&map, "UAL123").is_none());
     }
 
     // --- Unit conversions ---
@@ -600,6 +1092,113 @@ mod tests {
         assert!(a.callsign.is_none());
     }
 
+    #[test]
+    fn msg6_sets_squawk() {
+        let mut map = empty_map();
+        parse_sbs_line(&sbs_line(6, "ABC123", &[(17, "7700")]), &mut map);
+
+        let a = map.get("ABC123").unwrap();
+        assert_eq!(a.squawk.as_deref(), Some("7700"));
+    }
+
+    #[test]
+    fn ground_flag_is_tracked() {
+        let mut map = empty_map();
+        parse_sbs_line(&sbs_line(3, "ABC123", &[(21, "0")]), &mut map);
+        assert_eq!(map["ABC123"].on_ground, Some(false));
+        parse_sbs_line(&sbs_line(5, "ABC123", &[(21, "-1")]), &mut map);
+        assert_eq!(map["ABC123"].on_ground, Some(true));
+        parse_sbs_line(&sbs_line(1, "ABC123", &[(10, "TEST456")]), &mut map);
+        assert_eq!(map["ABC123"].on_ground, Some(true));
+    }
+
+    #[test]
+    fn position_messages_extend_trail() {
+        let mut map = empty_map();
+        parse_sbs_line(&sbs_line(5, "ABC123", &[(11, "5000")]), &mut map);
+        parse_sbs_line(&sbs_line(3, "ABC123", &[(14, "50.1"), (15, "-6.4")]), &mut map);
+        parse_sbs_line(&sbs_line(3, "ABC123", &[(11, "5200"), (14, "50.2"), (15, "-6.4")]), &mut map);
+        parse_sbs_line(&sbs_line(4, "ABC123", &[(12, "300")]), &mut map);
+
+        let trail: Vec<_> = map["ABC123"]
+            .trail
+            .iter()
+            .map(|p| (p.latitude, p.longitude, p.altitude_ft))
+            .collect();
+        assert_eq!(trail, vec![(50.1, -6.4, Some(5000.0)), (50.2, -6.4, Some(5200.0))]);
+    }
+
+    #[test]
+    fn messages_are_counted_by_type() {
+        let mut map = empty_map();
+        parse_sbs_line(&sbs_line(3, "ABC123", &[(14, "50.1"), (15, "-6.4")]), &mut map);
+        parse_sbs_line(&sbs_line(3, "ABC123", &[(14, "50.2"), (15, "-6.4")]), &mut map);
+        parse_sbs_line(&sbs_line(8, "ABC123", &[]), &mut map);
+        assert_eq!(map["ABC123"].messages, [0, 0, 2, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn field_update_times_are_kept() {
+        let mut map = empty_map();
+        parse_sbs_line(&sbs_line(4, "ABC123", &[(12, "300"), (16, "-640")]), &mut map);
+        let updated = map["ABC123"].updated;
+        assert!(updated.ground_speed_kt.is_some());
+        assert!(updated.vertical_rate_fpm.is_some());
+        assert_eq!(
+            FieldTimes {
+                ground_speed_kt: None,
+                vertical_rate_fpm: None,
+                ..updated
+            },
+            FieldTimes::default()
+        );
+
+        parse_sbs_line(&sbs_line(1, "ABC123", &[(10, "TEST456"), (21, "0")]), &mut map);
+        let updated = map["ABC123"].updated;
+        assert!(updated.callsign.is_some());
+        assert!(updated.on_ground.is_some());
+        assert!(updated.position.is_none());
+    }
+
+    #[test]
+    fn parse_returns_hex_ident() {
+        let mut map = empty_map();
+        let line = sbs_line(1, "ABC123", &[(10, "TEST456")]);
+        assert_eq!(parse_sbs_line(&line, &mut map), Some("ABC123"));
+        assert_eq!(parse_sbs_line("STA,,,,,,,,,,,,,,,,,,,,,", &mut map), None);
+    }
+
+    // --- Aircraft database ---
+
+    #[test]
+    fn annotate_from_database() {
+        let mut map = empty_map();
+        parse_sbs_line(&sbs_line(1, "abc123", &[(10, "TEST456")]), &mut map);
+        let db = AircraftDb::from([(
+            "ABC123".to_string(),
+            DbRecord {
+                registration: Some("N123AB".to_string()),
+                type_code: Some("C172".to_string()),
+            },
+        )]);
+
+        let a = map.get_mut("abc123").unwrap();
+        annotate_aircraft("abc123", a, &db);
+        assert_eq!(a.registration.as_deref(), Some("N123AB"));
+        assert_eq!(a.type_code.as_deref(), Some("C172"));
+    }
+
+    #[test]
+    fn annotate_unknown_aircraft() {
+        let mut map = empty_map();
+        parse_sbs_line(&sbs_line(1, "ABC123", &[(10, "TEST456")]), &mut map);
+
+        let a = map.get_mut("ABC123").unwrap();
+        annotate_aircraft("ABC123", a, &AircraftDb::new());
+        assert!(a.registration.is_none());
+        assert!(a.type_code.is_none());
+    }
+
     #[test]
     fn msg8_creates_entry_no_data() {
         let mut map = empty_map();
@@ -609,4 +1208,20 @@ mod tests {
         assert!(a.callsign.is_none());
         assert!(a.altitude_ft.is_none());
     }
+
+    #[test]
+    fn silent_aircraft_are_forgotten_unless_followed() {
+        let mut map = empty_map();
+        for hex in ["AAAAAA", "BBBBBB", "CCCCCC"] {
+            parse_sbs_line(&sbs_line(3, hex, &[(11, "5000"), (14, "40.0"), (15, "-74.0")]), &mut map);
+        }
+        assert_eq!(map["AAAAAA"].trail.iter().count(), 1);
+        let later = Instant::now() + FORGET_AFTER + Duration::from_secs(1);
+        map.get_mut("CCCCCC").unwrap().last_updated = later;
+
+        assert_eq!(forget_silent(&mut map, &["BBBBBB".to_string()], later), 1);
+        assert!(!map.contains_key("AAAAAA"));
+        assert!(map.contains_key("BBBBBB"));
+        assert!(map.contains_key("CCCCCC"));
+    }
 }
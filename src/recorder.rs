@@ -0,0 +1,377 @@
+use crate::attitude::Attitude;
+use crate::Fix;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const FEET_TO_METRES: f64 = 0.3048;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordFormat {
+    Gpx,
+    Kml,
+    Csv,
+}
+
+impl RecordFormat {
+    /// Also the file extension.
+    pub fn name(self) -> &'static str {
+        match self {
+            RecordFormat::Gpx => "gpx",
+            RecordFormat::Kml => "kml",
+            RecordFormat::Csv => "csv",
+        }
+    }
+
+    fn header(self, title: &str, time: SystemTime) -> String {
+        let title = escape_xml(title);
+        match self {
+            RecordFormat::Gpx => format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<gpx version=\"1.1\" creator=\"adsb_xgps\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n\
+<metadata><name>{title}</name><time>{}</time></metadata>\n\
+<trk><name>{title}</name><trkseg>\n",
+                iso8601(time)
+            ),
+            RecordFormat::Kml => format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document><name>{title}</name>\n\
+<Placemark><name>{title}</name><Style><LineStyle><color>ff66ff33</color><width>3</width></LineStyle></Style>\n\
+<LineString><altitudeMode>absolute</altitudeMode><coordinates>\n"
+            ),
+            RecordFormat::Csv => {
+                "time,latitude,longitude,altitude_ft,track,ground_speed_kt,vertical_rate_fpm,pitch_deg,roll_deg\n"
+                    .to_string()
+            }
+        }
+    }
+
+    fn point(self, fix: &Fix, attitude: &Attitude, time: SystemTime) -> String {
+        match self {
+            RecordFormat::Gpx => format!(
+                "<trkpt lat=\"{:.6}\" lon=\"{:.6}\"><ele>{:.1}</ele><time>{}</time></trkpt>\n",
+                fix.latitude,
+                fix.longitude,
+                fix.altitude_ft * FEET_TO_METRES,
+                iso8601(time)
+            ),
+            RecordFormat::Kml => format!(
+                "{:.6},{:.6},{:.1}\n",
+                fix.longitude,
+                fix.latitude,
+                fix.altitude_ft * FEET_TO_METRES
+            ),
+            RecordFormat::Csv => format!(
+                "{},{:.6},{:.6},{:.0},{:.1},{:.1},{},{:.2},{:.2}\n",
+                iso8601(time),
+                fix.latitude,
+                fix.longitude,
+                fix.altitude_ft,
+                fix.track,
+                fix.ground_speed_kt,
+                fix.vertical_rate_fpm.map_or(String::new(), |v| format!("{:.0}", v)),
+                attitude.pitch_deg,
+                attitude.roll_deg
+            ),
+        }
+    }
+
+    /// Closing text, rewritten after every point so the file is always
+    /// complete.
+    fn footer(self) -> &'static str {
+        match self {
+            RecordFormat::Gpx => "</trkseg></trk>\n</gpx>\n",
+            RecordFormat::Kml => "</coordinates></LineString></Placemark>\n</Document>\n</kml>\n",
+            RecordFormat::Csv => "",
+        }
+    }
+}
+
+impl fmt::Display for RecordFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for RecordFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gpx" => Ok(RecordFormat::Gpx),
+            "kml" => Ok(RecordFormat::Kml),
+            "csv" => Ok(RecordFormat::Csv),
+            _ => Err(format!("unknown recording format '{}' (expected gpx, kml or csv)", s)),
+        }
+    }
+}
+
+/// Whether a file name looks like one of ours, for listing and download.
+pub fn is_recording(name: &str) -> bool {
+    !name.starts_with('.')
+        && !name.contains(['/', '\\'])
+        && name
+            .rsplit_once('.')
+            .is_some_and(|(_, ext)| ext.parse::<RecordFormat>().is_ok())
+}
+
+struct LogFile {
+    format: RecordFormat,
+    path: PathBuf,
+    file: File,
+}
+
+impl LogFile {
+    fn create(dir: &Path, stem: &str, format: RecordFormat, title: &str, time: SystemTime) -> io::Result<Self> {
+        let mut n = 1;
+        loop {
+            let name = match n {
+                1 => format!("{}.{}", stem, format),
+                _ => format!("{}-{}.{}", stem, n, format),
+            };
+            let path = dir.join(name);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(format.header(title, time).as_bytes())?;
+                    file.write_all(format.footer().as_bytes())?;
+                    return Ok(LogFile { format, path, file });
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn append(&mut self, fix: &Fix, attitude: &Attitude, time: SystemTime) -> io::Result<()> {
+        let footer = self.format.footer();
+        self.file.seek(SeekFrom::End(-(footer.len() as i64)))?;
+        self.file.write_all(self.format.point(fix, attitude, time).as_bytes())?;
+        self.file.write_all(footer.as_bytes())
+    }
+}
+
+/// Files for one slot, kept while it follows the same target and aircraft.
+struct Session {
+    target: String,
+    hex: Option<String>,
+    files: Vec<LogFile>,
+}
+
+/// Writes what each slot sends over XGPS to files in a directory. A new
+/// set of files starts whenever a slot's target or aircraft changes.
+pub struct Recorder {
+    dir: PathBuf,
+    formats: Vec<RecordFormat>,
+    sessions: HashMap<String, Session>,
+}
+
+impl Recorder {
+    pub fn new(dir: PathBuf, formats: Vec<RecordFormat>) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Recorder {
+            dir,
+            formats,
+            sessions: HashMap::new(),
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        slot: &str,
+        target: &str,
+        hex: Option<&str>,
+        fix: &Fix,
+        attitude: &Attitude,
+        time: SystemTime,
+    ) {
+        let current = self
+            .sessions
+            .get(slot)
+            .is_some_and(|s| s.target == target && s.hex.as_deref() == hex);
+        if !current {
+            let session = self.start(slot, target, hex, time);
+            self.sessions.insert(slot.to_string(), session);
+        }
+        let session = self.sessions.get_mut(slot).expect("session just started");
+        session.files.retain_mut(|f| match f.append(fix, attitude, time) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Recorder: failed to write {}: {}, closing it", f.path.display(), e);
+                false
+            }
+        });
+    }
+
+    fn start(&self, slot: &str, target: &str, hex: Option<&str>, time: SystemTime) -> Session {
+        let hex_name = hex.unwrap_or("unknown");
+        let stem = format!(
+            "{}-{}-{}-{}",
+            compact_timestamp(time),
+            slot,
+            file_safe(target),
+            file_safe(hex_name)
+        );
+        let title = format!("{} {} ({})", slot, target, hex_name);
+        let mut files = Vec::new();
+        for &format in &self.formats {
+            match LogFile::create(&self.dir, &stem, format, &title, time) {
+                Ok(f) => {
+                    println!("Recorder: slot {} recording to {}", slot, f.path.display());
+                    files.push(f);
+                }
+                Err(e) => eprintln!("Recorder: failed to create {} file in {}: {}", format, self.dir.display(), e),
+            }
+        }
+        Session {
+            target: target.to_string(),
+            hex: hex.map(str::to_string),
+            files,
+        }
+    }
+}
+
+fn file_safe(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// UTC year, month, day, hour, minute, second; days to civil date after
+/// Howard Hinnant's algorithm.
+fn utc(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day, (rem / 3600) as u32, (rem / 60 % 60) as u32, (rem % 60) as u32)
+}
+
+fn iso8601(time: SystemTime) -> String {
+    let (y, mo, d, h, mi, s) = utc(time);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", y, mo, d, h, mi, s)
+}
+
+fn compact_timestamp(time: SystemTime) -> String {
+    let (y, mo, d, h, mi, s) = utc(time);
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", y, mo, d, h, mi, s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn fix(lat: f64) -> Fix {
+        Fix {
+            latitude: lat,
+            longitude: -74.0,
+            altitude_ft: 5000.0,
+            track: 90.0,
+            ground_speed_kt: 250.0,
+            vertical_rate_fpm: Some(-500.0),
+        }
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("adsb_xgps_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn timestamps_are_utc() {
+        assert_eq!(iso8601(at(0)), "1970-01-01T00:00:00Z");
+        assert_eq!(iso8601(at(951_782_400)), "2000-02-29T00:00:00Z");
+        assert_eq!(iso8601(at(1_792_323_045)), "2026-10-18T11:30:45Z");
+        assert_eq!(compact_timestamp(at(1_792_323_045)), "20261018T113045Z");
+    }
+
+    #[test]
+    fn recording_name_check() {
+        assert!(is_recording("20261018T113045Z-main-UAL1-A1B2C3.gpx"));
+        assert!(is_recording("x.CSV"));
+        assert!(!is_recording("notes.txt"));
+        assert!(!is_recording("../secret.gpx"));
+        assert!(!is_recording(".hidden.gpx"));
+    }
+
+    #[test]
+    fn gpx_is_complete_after_every_point() {
+        let dir = temp_dir("gpx");
+        let mut recorder = Recorder::new(dir.clone(), vec![RecordFormat::Gpx, RecordFormat::Csv]).unwrap();
+        recorder.record("main", "UAL1", Some("A1B2C3"), &fix(40.0), &Attitude::default(), at(1_792_323_045));
+        recorder.record("main", "UAL1", Some("A1B2C3"), &fix(40.1), &Attitude::default(), at(1_792_323_046));
+
+        assert_eq!(
+            files(&dir),
+            vec!["20261018T113045Z-main-UAL1-A1B2C3.csv", "20261018T113045Z-main-UAL1-A1B2C3.gpx"]
+        );
+        let gpx = fs::read_to_string(dir.join("20261018T113045Z-main-UAL1-A1B2C3.gpx")).unwrap();
+        assert!(gpx.starts_with("<?xml"));
+        assert!(gpx.contains(
+            "<trkseg>\n<trkpt lat=\"40.000000\" lon=\"-74.000000\"><ele>1524.0</ele><time>2026-10-18T11:30:45Z</time></trkpt>\n\
+<trkpt lat=\"40.100000\" lon=\"-74.000000\"><ele>1524.0</ele><time>2026-10-18T11:30:46Z</time></trkpt>\n</trkseg>"
+        ));
+        assert!(gpx.ends_with("</trkseg></trk>\n</gpx>\n"));
+        let csv = fs::read_to_string(dir.join("20261018T113045Z-main-UAL1-A1B2C3.csv")).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert_eq!(
+            csv.lines().nth(1).unwrap(),
+            "2026-10-18T11:30:45Z,40.000000,-74.000000,5000,90.0,250.0,-500,0.00,0.00"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn new_target_or_aircraft_splits_files() {
+        let dir = temp_dir("split");
+        let mut recorder = Recorder::new(dir.clone(), vec![RecordFormat::Kml]).unwrap();
+        recorder.record("main", "UAL1", Some("A1B2C3"), &fix(40.0), &Attitude::default(), at(100));
+        recorder.record("main", "UAL1", Some("A1B2C3"), &fix(40.1), &Attitude::default(), at(101));
+        recorder.record("main", "UAL1", Some("D4E5F6"), &fix(40.2), &Attitude::default(), at(102));
+        recorder.record("main", "reg:N1", Some("D4E5F6"), &fix(40.3), &Attitude::default(), at(102));
+        recorder.record("second", "UAL1", Some("A1B2C3"), &fix(40.0), &Attitude::default(), at(102));
+
+        assert_eq!(
+            files(&dir),
+            vec![
+                "19700101T000140Z-main-UAL1-A1B2C3.kml",
+                "19700101T000142Z-main-UAL1-D4E5F6.kml",
+                "19700101T000142Z-main-reg_N1-D4E5F6.kml",
+                "19700101T000142Z-second-UAL1-A1B2C3.kml",
+            ]
+        );
+        let kml = fs::read_to_string(dir.join("19700101T000140Z-main-UAL1-A1B2C3.kml")).unwrap();
+        assert!(kml.contains("<coordinates>\n-74.000000,40.000000,1524.0\n-74.000000,40.100000,1524.0\n</coordinates>"));
+        assert!(kml.ends_with("</kml>\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1706 @@
+use crate::{Aircraft, Fix};
+use regex::{Regex, RegexBuilder};
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::time::{Duration, Instant};
+
+/// Which aircraft to follow. Written as `CALLSIGN`, `hex:ICAO`,
+/// `reg:REGISTRATION` or `squawk:CODE`; values are kept upper case.
+/// Callsigns and registrations may also be a glob (`UAL*`, `N12?`) or a
+/// regular expression between slashes (`/^UAL\d{3}$/`).
+/// `near:LAT,LON[,MIN-MAX]` follows whichever aircraft is closest to a
+/// point, optionally only within an altitude band in feet.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    Callsign(Pattern),
+    Hex(String),
+    Registration(Pattern),
+    Squawk(String),
+    Nearest {
+        lat: f64,
+        lon: f64,
+        band: Option<(f64, f64)>,
+    },
+}
+
+/// How a callsign or registration target is compared, always ignoring case.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Exact(String),
+    Glob(String),
+    Regex(Regex),
+}
+
+/// Longest pattern accepted. Callsigns and registrations are short, and
+/// targets arrive from the web interface.
+const MAX_PATTERN_LEN: usize = 64;
+
+impl Pattern {
+    fn parse(s: &str) -> Result<Self, String> {
+        if s.len() > MAX_PATTERN_LEN {
+            return Err(format!("pattern is longer than {} characters", MAX_PATTERN_LEN));
+        }
+        if let Some(re) = s.strip_prefix('/').and_then(|r| r.strip_suffix('/')) {
+            return RegexBuilder::new(re)
+                .case_insensitive(true)
+                .build()
+                .map(Pattern::Regex)
+                .map_err(|e| format!("invalid regex '{}': {}", re, e));
+        }
+        let mut value = s.to_ascii_uppercase();
+        if value.contains(['*', '?']) {
+            // Runs of stars match the same as one
+            while value.contains("**") {
+                value = value.replace("**", "*");
+            }
+            Ok(Pattern::Glob(value))
+        } else {
+            Ok(Pattern::Exact(value))
+        }
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Pattern::Exact(want) => value.eq_ignore_ascii_case(want),
+            Pattern::Glob(glob) => glob_matches(glob.as_bytes(), value.to_ascii_uppercase().as_bytes()),
+            Pattern::Regex(re) => re.is_match(value),
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Pattern::Exact(_))
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Pattern::Exact(a), Pattern::Exact(b)) | (Pattern::Glob(a), Pattern::Glob(b)) => a == b,
+            (Pattern::Regex(a), Pattern::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Exact(s) | Pattern::Glob(s) => write!(f, "{}", s),
+            Pattern::Regex(re) => write!(f, "/{}/", re.as_str()),
+        }
+    }
+}
+
+/// `*` matches any run of characters, `?` exactly one. On a mismatch only
+/// the last star is retried one character further on, which keeps this
+/// O(glob * value) however many stars there are.
+fn glob_matches(glob: &[u8], value: &[u8]) -> bool {
+    let (mut g, mut v) = (0, 0);
+    // Position after the last star, and where in `value` it matched up to
+    let mut star: Option<(usize, usize)> = None;
+    while v < value.len() {
+        match glob.get(g) {
+            Some(b'*') => {
+                star = Some((g + 1, v));
+                g += 1;
+            }
+            Some(c) if *c == b'?' || *c == value[v] => {
+                g += 1;
+                v += 1;
+            }
+            _ => match star {
+                Some((after, matched)) => {
+                    star = Some((after, matched + 1));
+                    g = after;
+                    v = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|c| *c == b'*')
+}
+
+impl Target {
+    pub fn matches(&self, hex: &str, aircraft: &Aircraft) -> bool {
+        let eq = |field: Option<&str>, want: &str| field.is_some_and(|v| v.eq_ignore_ascii_case(want));
+        match self {
+            Target::Callsign(p) => aircraft.callsign.as_deref().is_some_and(|cs| p.matches(cs)),
+            Target::Hex(h) => hex.eq_ignore_ascii_case(h),
+            Target::Registration(p) => aircraft
+                .registration
+                .as_deref()
+                .is_some_and(|reg| p.matches(reg)),
+            Target::Squawk(code) => eq(aircraft.squawk.as_deref(), code),
+            Target::Nearest { band, .. } => {
+                let has_position = aircraft.latitude.is_some() && aircraft.longitude.is_some();
+                let in_band = match band {
+                    Some((min, max)) => aircraft
+                        .altitude_ft
+                        .is_some_and(|alt| (*min..=*max).contains(&alt)),
+                    None => true,
+                };
+                has_position && in_band
+            }
+        }
+    }
+
+    /// Whether the target names one aircraft, so several matches are a
+    /// conflict rather than expected.
+    pub fn is_exact(&self) -> bool {
+        match self {
+            Target::Callsign(p) | Target::Registration(p) => p.is_exact(),
+            Target::Hex(_) | Target::Squawk(_) => true,
+            Target::Nearest { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Callsign(p) => write!(f, "{}", p),
+            Target::Hex(h) => write!(f, "hex:{}", h),
+            Target::Registration(p) => write!(f, "reg:{}", p),
+            Target::Squawk(code) => write!(f, "squawk:{}", code),
+            Target::Nearest { lat, lon, band } => {
+                write!(f, "near:{},{}", lat, lon)?;
+                if let Some((min, max)) = band {
+                    write!(f, ",{}-{}", min, max)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        // A regex may itself contain colons, so only look for a kind prefix
+        // before it
+        let (kind, value) = match s.split_once(':') {
+            Some((kind, value)) if !kind.starts_with('/') => (kind, value.trim()),
+            _ => ("callsign", s),
+        };
+        if value.is_empty() {
+            return Err("empty target".to_string());
+        }
+
+        match kind.to_ascii_lowercase().as_str() {
+            "callsign" | "cs" => Ok(Target::Callsign(Pattern::parse(value)?)),
+            "hex" | "icao" => {
+                let value = value.to_ascii_uppercase();
+                if value.len() != 6 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(format!("'{}' is not a 6 digit ICAO hex address", value));
+                }
+                Ok(Target::Hex(value))
+            }
+            "reg" | "registration" => Ok(Target::Registration(Pattern::parse(value)?)),
+            "squawk" | "sq" => {
+                if value.len() != 4 || !value.chars().all(|c| ('0'..='7').contains(&c)) {
+                    return Err(format!("'{}' is not a 4 digit octal squawk", value));
+                }
+                Ok(Target::Squawk(value.to_string()))
+            }
+            "near" => {
+                let (point, band) = match value.splitn(3, ',').nth(2) {
+                    Some(band) => (&value[..value.len() - band.len() - 1], Some(band)),
+                    None => (value, None),
+                };
+                let (lat, lon) = parse_position(point)?;
+                let band = match band {
+                    Some(band) => {
+                        let (min, max) = band
+                            .split_once('-')
+                            .and_then(|(min, max)| Some((min.trim().parse().ok()?, max.trim().parse().ok()?)))
+                            .ok_or_else(|| format!("'{}' is not an altitude band MIN-MAX", band))?;
+                        if min > max {
+                            return Err(format!("altitude band {}-{} is inverted", min, max));
+                        }
+                        Some((min, max))
+                    }
+                    None => None,
+                };
+                Ok(Target::Nearest { lat, lon, band })
+            }
+            _ => Err(format!(
+                "unknown target kind '{}' (expected callsign, hex, reg, squawk or near)",
+                kind
+            )),
+        }
+    }
+}
+
+/// How to choose between several aircraft matching the target when none is
+/// being followed yet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SelectionRule {
+    /// Most recently heard, then closest to the last position sent
+    #[default]
+    Freshest,
+    /// Closest to the receiver
+    Nearest,
+    /// Lowest altitude
+    Lowest,
+}
+
+impl fmt::Display for SelectionRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SelectionRule::Freshest => "freshest",
+            SelectionRule::Nearest => "nearest",
+            SelectionRule::Lowest => "lowest",
+        })
+    }
+}
+
+impl FromStr for SelectionRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "freshest" => Ok(SelectionRule::Freshest),
+            "nearest" => Ok(SelectionRule::Nearest),
+            "lowest" => Ok(SelectionRule::Lowest),
+            _ => Err(format!(
+                "unknown selection rule '{}' (expected freshest, nearest or lowest)",
+                s
+            )),
+        }
+    }
+}
+
+/// What to send once the followed aircraft goes stale.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LossMode {
+    /// Stop sending
+    #[default]
+    Stop,
+    /// Keep sending the last fix
+    Hold,
+    /// Extrapolate along the last track and speed for this long, then stop
+    Extrapolate(Duration),
+}
+
+pub const DEFAULT_EXTRAPOLATE: Duration = Duration::from_secs(30);
+
+impl fmt::Display for LossMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LossMode::Stop => f.write_str("stop"),
+            LossMode::Hold => f.write_str("hold"),
+            LossMode::Extrapolate(limit) => write!(f, "extrapolate:{}", limit.as_secs()),
+        }
+    }
+}
+
+impl FromStr for LossMode {
+    type Err = String;
+
+    /// `stop`, `hold` or `extrapolate[:SECS]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mode, secs) = match s.split_once(':') {
+            Some((mode, secs)) => (mode, Some(secs)),
+            None => (s, None),
+        };
+        match (mode.to_ascii_lowercase().as_str(), secs) {
+            ("stop", None) => Ok(LossMode::Stop),
+            ("hold", None) => Ok(LossMode::Hold),
+            ("extrapolate", None) => Ok(LossMode::Extrapolate(DEFAULT_EXTRAPOLATE)),
+            ("extrapolate", Some(secs)) => secs
+                .parse()
+                .map(|secs| LossMode::Extrapolate(Duration::from_secs(secs)))
+                .map_err(|_| format!("invalid extrapolation time '{}'", secs)),
+            _ => Err(format!(
+                "unknown loss mode '{}' (expected stop, hold or extrapolate[:SECS])",
+                s
+            )),
+        }
+    }
+}
+
+/// Where the tracker stands with its output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrackStatus {
+    /// No fix yet for the current target
+    #[default]
+    Acquiring,
+    Tracking,
+    /// Lost, still sending the last fix
+    Holding,
+    /// Lost, sending an extrapolated fix
+    Extrapolating,
+    /// Lost, nothing being sent
+    Lost,
+    /// Stopped on request until a new target is set
+    Stopped,
+}
+
+impl TrackStatus {
+    pub fn is_lost(&self) -> bool {
+        matches!(self, TrackStatus::Holding | TrackStatus::Extrapolating | TrackStatus::Lost)
+    }
+}
+
+impl fmt::Display for TrackStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            TrackStatus::Acquiring => "acquiring",
+            TrackStatus::Tracking => "tracking",
+            TrackStatus::Holding => "holding",
+            TrackStatus::Extrapolating => "extrapolating",
+            TrackStatus::Lost => "lost",
+            TrackStatus::Stopped => "stopped",
+        })
+    }
+}
+
+/// When to move on from the current target to the next one on the watch
+/// list.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SwitchCondition {
+    /// The aircraft reports being on the ground after having been airborne
+    Landed,
+    /// Nothing heard from the target for this long
+    Stale(Duration),
+    /// The aircraft descends below this altitude in feet
+    Below(f64),
+}
+
+impl fmt::Display for SwitchCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SwitchCondition::Landed => f.write_str("landed"),
+            SwitchCondition::Stale(d) => write!(f, "stale:{}", d.as_secs() / 60),
+            SwitchCondition::Below(alt) => write!(f, "below:{}", alt),
+        }
+    }
+}
+
+impl FromStr for SwitchCondition {
+    type Err = String;
+
+    /// `landed`, `stale:MINUTES` or `below:FEET`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (kind, value) = match s.split_once(':') {
+            Some((kind, value)) => (kind, Some(value.trim())),
+            None => (s, None),
+        };
+        match (kind.to_ascii_lowercase().as_str(), value) {
+            ("landed" | "ground", None) => Ok(SwitchCondition::Landed),
+            ("stale", Some(mins)) => mins
+                .parse::<u64>()
+                .map(|mins| SwitchCondition::Stale(Duration::from_secs(mins * 60)))
+                .map_err(|_| format!("invalid stale time '{}' (minutes)", mins)),
+            ("below", Some(alt)) => alt
+                .parse()
+                .map(SwitchCondition::Below)
+                .map_err(|_| format!("invalid altitude '{}'", alt)),
+            _ => Err(format!(
+                "unknown switch condition '{}' (expected landed, stale:MINUTES or below:FEET)",
+                s
+            )),
+        }
+    }
+}
+
+/// Parse a comma separated list of switch conditions; empty means none.
+pub fn parse_conditions(s: &str) -> Result<Vec<SwitchCondition>, String> {
+    s.split(',')
+        .filter(|c| !c.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// A queued target and the conditions that end it, written as
+/// `TARGET[ until COND[,COND...]]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchEntry {
+    pub target: Target,
+    pub until: Vec<SwitchCondition>,
+}
+
+impl fmt::Display for WatchEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.target)?;
+        if !self.until.is_empty() {
+            write!(f, " until {}", format_conditions(&self.until))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for WatchEntry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, until) = match s.trim().rsplit_once(" until ") {
+            Some((target, until)) => (target, parse_conditions(until)?),
+            None => (s, Vec::new()),
+        };
+        Ok(WatchEntry {
+            target: target.parse()?,
+            until,
+        })
+    }
+}
+
+pub fn format_conditions(conditions: &[SwitchCondition]) -> String {
+    conditions
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Move a fix along its track at its ground speed, and vertical rate if
+/// known, for `secs` seconds.
+pub fn extrapolate(fix: &Fix, secs: f64) -> Fix {
+    const EARTH_RADIUS_NM: f64 = 3440.065;
+    let d = fix.ground_speed_kt * secs / 3600.0 / EARTH_RADIUS_NM;
+    let brg = fix.track.to_radians();
+    let lat1 = fix.latitude.to_radians();
+    let lon1 = fix.longitude.to_radians();
+    let lat2 = (lat1.sin() * d.cos() + lat1.cos() * d.sin() * brg.cos()).asin();
+    let lon2 = lon1 + (brg.sin() * d.sin() * lat1.cos()).atan2(d.cos() - lat1.sin() * lat2.sin());
+    Fix {
+        latitude: lat2.to_degrees(),
+        longitude: (lon2.to_degrees() + 540.0) % 360.0 - 180.0,
+        altitude_ft: fix.altitude_ft + fix.vertical_rate_fpm.unwrap_or(0.0) * secs / 60.0,
+        ..*fix
+    }
+}
+
+/// Parse `LAT,LON` in decimal degrees.
+pub fn parse_position(s: &str) -> Result<(f64, f64), String> {
+    let (lat, lon) = s
+        .split_once(',')
+        .ok_or_else(|| format!("'{}' is not LAT,LON", s))?;
+    let lat: f64 = lat
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid latitude '{}'", lat))?;
+    let lon: f64 = lon
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid longitude '{}'", lon))?;
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(format!("position {},{} out of range", lat, lon));
+    }
+    Ok((lat, lon))
+}
+
+/// Aircraft not heard from for this long are not followed.
+pub const STALE_AFTER: Duration = Duration::from_secs(5);
+
+pub const DEFAULT_HYSTERESIS_NM: f64 = 1.0;
+
+/// Follows the aircraft matching a target and decides deterministically
+/// between several matches: the aircraft already being followed wins while
+/// it is fresh, then the selection rule decides, with any remaining tie
+/// going to the lowest hex.
+///
+/// With `lock` set, the first aircraft acquired is pinned by hex and followed
+/// even when it stops matching the target, e.g. after an ATC callsign
+/// change, until the lock is released.
+pub struct Tracker {
+    pub target: Target,
+    /// Hex of the aircraft currently followed
+    pub current: Option<String>,
+    pub lock: bool,
+    /// Hex pinned by the lock once acquired
+    pub locked: Option<String>,
+    pub rule: SelectionRule,
+    /// Receiver position for the nearest rule
+    pub receiver: Option<(f64, f64)>,
+    /// How much closer another aircraft must be before a `near:` target
+    /// switches to it
+    pub hysteresis_nm: f64,
+    pub on_loss: LossMode,
+    pub status: TrackStatus,
+    /// Conditions that end the current target and move on to the watch list
+    pub until: Vec<SwitchCondition>,
+    /// Targets to follow next, in order
+    pub watch: VecDeque<WatchEntry>,
+    /// When the current target was set, for stale checks before any fix
+    target_since: Instant,
+    /// The followed aircraft has reported being airborne
+    seen_airborne: bool,
+    /// Highest altitude the followed aircraft has reported
+    max_altitude: Option<f64>,
+    last_position: Option<(f64, f64)>,
+    /// Last complete fix and when its aircraft was heard
+    last_fix: Option<(Fix, Instant)>,
+}
+
+pub type SharedTracker = Arc<RwLock<Tracker>>;
+
+/// Name of the slot the target given on the command line goes into.
+pub const PRIMARY_SLOT: &str = "main";
+
+/// A named tracker. Every XGPS destination is fed by exactly one slot, so
+/// several aircraft can be followed at once.
+pub struct Slot {
+    pub name: String,
+    pub tracker: SharedTracker,
+}
+
+/// Tracking slots, fixed at startup; the first is the primary slot.
+pub type Slots = Arc<Vec<Slot>>;
+
+/// Index of the slot called `name`, or of the primary slot when no name is
+/// given.
+pub fn slot_index(slots: &[Slot], name: Option<&str>) -> Option<usize> {
+    match name {
+        Some(name) => slots.iter().position(|s| s.name == name),
+        None => (!slots.is_empty()).then_some(0),
+    }
+}
+
+/// Check a slot name: letters, digits, `-` and `_` only.
+pub fn parse_slot_name(s: &str) -> Result<String, String> {
+    let s = s.trim();
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("invalid slot name '{}' (letters, digits, - and _ only)", s));
+    }
+    Ok(s.to_string())
+}
+
+/// Parse `NAME=TARGET` for an extra tracking slot.
+pub fn parse_slot(s: &str) -> Result<(String, Target), String> {
+    let (name, target) = s
+        .split_once('=')
+        .ok_or_else(|| format!("'{}' is not NAME=TARGET", s))?;
+    Ok((parse_slot_name(name)?, target.parse()?))
+}
+
+/// Result of matching the target against all aircraft.
+pub struct Selection<'a> {
+    pub chosen: Option<(&'a String, &'a Aircraft)>,
+    /// Hex of every aircraft matching the target, sorted
+    pub matched: Vec<&'a String>,
+    exact: bool,
+}
+
+impl Selection<'_> {
+    /// Several aircraft match a target meant to name just one.
+    pub fn is_conflict(&self) -> bool {
+        self.exact && self.matched.len() > 1
+    }
+}
+
+impl Tracker {
+    pub fn new(target: Target) -> Self {
+        Tracker {
+            target,
+            current: None,
+            lock: false,
+            locked: None,
+            rule: SelectionRule::default(),
+            receiver: None,
+            hysteresis_nm: DEFAULT_HYSTERESIS_NM,
+            on_loss: LossMode::default(),
+            status: TrackStatus::default(),
+            until: Vec::new(),
+            watch: VecDeque::new(),
+            target_since: Instant::now(),
+            seen_airborne: false,
+            max_altitude: None,
+            last_position: None,
+            last_fix: None,
+        }
+    }
+
+    /// Switch to a new target. An armed lock stays armed and pins whatever
+    /// the new target acquires.
+    pub fn set_target(&mut self, target: Target) {
+        self.target = target;
+        self.current = None;
+        self.locked = None;
+        self.last_position = None;
+        self.last_fix = None;
+        self.status = TrackStatus::Acquiring;
+        self.until.clear();
+        self.target_since = Instant::now();
+        self.seen_airborne = false;
+        self.max_altitude = None;
+    }
+
+    /// Follow the next entry on the watch list, if any.
+    pub fn advance(&mut self) -> Option<&Target> {
+        let next = self.watch.pop_front()?;
+        self.set_target(next.target);
+        self.until = next.until;
+        Some(&self.target)
+    }
+
+    /// Check the switch conditions against the followed aircraft, returning
+    /// the one met.
+    fn switch_reason(&mut self, map: &HashMap<String, Aircraft>, now: Instant) -> Option<String> {
+        let aircraft = self.current.as_ref().and_then(|hex| map.get(hex));
+        let fresh = aircraft.filter(|a| now.saturating_duration_since(a.last_updated) <= STALE_AFTER);
+        if let Some(a) = fresh {
+            if a.on_ground == Some(false) {
+                self.seen_airborne = true;
+            }
+            if let Some(alt) = a.altitude_ft {
+                self.max_altitude = Some(self.max_altitude.map_or(alt, |max| max.max(alt)));
+            }
+        }
+        let heard = aircraft.map_or(self.target_since, |a| a.last_updated.max(self.target_since));
+
+        self.until.iter().find_map(|condition| match *condition {
+            SwitchCondition::Landed => (self.seen_airborne
+                && fresh.is_some_and(|a| a.on_ground == Some(true)))
+            .then(|| "landed".to_string()),
+            SwitchCondition::Stale(limit) => (now.saturating_duration_since(heard) >= limit)
+                .then(|| format!("stale for {} min", limit.as_secs() / 60)),
+            SwitchCondition::Below(floor) => (self.max_altitude.is_some_and(|max| max >= floor)
+                && fresh.and_then(|a| a.altitude_ft).is_some_and(|alt| alt < floor))
+            .then(|| format!("below {} ft", floor)),
+        })
+    }
+
+    /// How long since the last complete fix, while the track is lost.
+    pub fn lost_for(&self) -> Option<Duration> {
+        match self.last_fix {
+            Some((_, heard)) if self.status.is_lost() => Some(heard.elapsed()),
+            _ => None,
+        }
+    }
+
+    /// Arm the lock, pinning the aircraft currently followed if there is one.
+    pub fn lock(&mut self) {
+        self.lock = true;
+        if self.locked.is_none() {
+            self.locked = self.current.clone();
+        }
+    }
+
+    pub fn release(&mut self) {
+        self.lock = false;
+        self.locked = None;
+    }
+
+    /// Stop sending anything for this target; setting a target resumes.
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.locked = None;
+        self.last_fix = None;
+        self.status = TrackStatus::Stopped;
+    }
+
+    pub fn select<'a>(&self, map: &'a HashMap<String, Aircraft>) -> Selection<'a> {
+        let mut matched: Vec<(&String, &Aircraft)> = map
+            .iter()
+            .filter(|(hex, a)| self.target.matches(hex, a))
+            .collect();
+        matched.sort_by(|x, y| x.0.cmp(y.0));
+
+        if self.status == TrackStatus::Stopped {
+            return Selection {
+                chosen: None,
+                matched: matched.into_iter().map(|(hex, _)| hex).collect(),
+                exact: self.target.is_exact(),
+            };
+        }
+
+        if let Some(locked) = &self.locked {
+            return Selection {
+                chosen: map.get_key_value(locked),
+                matched: matched.into_iter().map(|(hex, _)| hex).collect(),
+                exact: self.target.is_exact(),
+            };
+        }
+
+        let sticky = self.current.as_ref().and_then(|cur| {
+            matched
+                .iter()
+                .find(|(hex, a)| *hex == cur && a.last_updated.elapsed() <= STALE_AFTER)
+                .copied()
+        });
+
+        let chosen = if let Target::Nearest { lat, lon, .. } = self.target {
+            // Re-evaluated every time; the current aircraft is only dropped
+            // for one clearly closer, so two nearly equidistant aircraft
+            // don't make it flip back and forth
+            let dist = |a: &Aircraft| match (a.latitude, a.longitude) {
+                (Some(alat), Some(alon)) => distance_nm(lat, lon, alat, alon),
+                _ => f64::INFINITY,
+            };
+            let nearest = matched
+                .iter()
+                .filter(|(_, a)| a.last_updated.elapsed() <= STALE_AFTER)
+                .min_by(|x, y| dist(x.1).total_cmp(&dist(y.1)))
+                .copied();
+            match (sticky, nearest) {
+                (Some(cur), Some(near)) if dist(near.1) + self.hysteresis_nm >= dist(cur.1) => {
+                    Some(cur)
+                }
+                (cur, near) => near.or(cur),
+            }
+        } else {
+            // min_by keeps the first of equal elements, so remaining ties go
+            // to the lowest hex
+            sticky.or_else(|| {
+                matched
+                    .iter()
+                    .min_by(|x, y| self.compare(x.1, y.1))
+                    .copied()
+            })
+        };
+
+        Selection {
+            chosen,
+            matched: matched.into_iter().map(|(hex, _)| hex).collect(),
+            exact: self.target.is_exact(),
+        }
+    }
+
+    /// Order two candidates by the selection rule, best first.
+    fn compare(&self, x: &Aircraft, y: &Aircraft) -> Ordering {
+        let age = |a: &Aircraft| a.last_updated.elapsed().as_secs();
+        let dist_from = |from: Option<(f64, f64)>, a: &Aircraft| match (from, a.latitude, a.longitude) {
+            (Some((lat0, lon0)), Some(lat), Some(lon)) => distance_nm(lat0, lon0, lat, lon),
+            _ => f64::INFINITY,
+        };
+        let altitude = |a: &Aircraft| a.altitude_ft.unwrap_or(f64::INFINITY);
+
+        match self.rule {
+            SelectionRule::Freshest => age(x).cmp(&age(y)).then(
+                dist_from(self.last_position, x).total_cmp(&dist_from(self.last_position, y)),
+            ),
+            SelectionRule::Nearest => dist_from(self.receiver, x)
+                .total_cmp(&dist_from(self.receiver, y))
+                .then(age(x).cmp(&age(y))),
+            SelectionRule::Lowest => altitude(x)
+                .total_cmp(&altitude(y))
+                .then(age(x).cmp(&age(y))),
+        }
+    }
+
+    /// Select the aircraft to follow and return the fix to send: its own
+    /// while fresh and complete, otherwise whatever the loss mode calls for.
+    pub fn update(&mut self, map: &HashMap<String, Aircraft>) -> Option<Fix> {
+        if self.status == TrackStatus::Stopped {
+            return None;
+        }
+        let now = Instant::now();
+        if let Some(reason) = self.switch_reason(map, now) {
+            let hex = self.current.clone().unwrap_or_else(|| "-".to_string());
+            let from = self.target.to_string();
+            if let Some(next) = self.advance() {
+                println!("Tracker: {} for {} {}, switching to {}", hex, from, reason, next);
+            }
+        }
+        match self.live_fix(map) {
+            Some((fix, heard)) => {
+                if self.status.is_lost() {
+                    let (_, last) = self.last_fix.unwrap_or((fix, heard));
+                    println!(
+                        "Tracker: recovered {} for {} after {}s",
+                        self.current.as_deref().unwrap_or("-"),
+                        self.target,
+                        heard.saturating_duration_since(last).as_secs()
+                    );
+                }
+                self.status = TrackStatus::Tracking;
+                self.last_fix = Some((fix, heard));
+                Some(fix)
+            }
+            None => self.coast(now),
+        }
+    }
+
+    /// Apply the loss mode to the last fix, logging each change of status.
+    fn coast(&mut self, now: Instant) -> Option<Fix> {
+        let (fix, heard) = self.last_fix?;
+        let since = now.saturating_duration_since(heard);
+        let status = match self.on_loss {
+            LossMode::Stop => TrackStatus::Lost,
+            LossMode::Hold => TrackStatus::Holding,
+            LossMode::Extrapolate(limit) if since <= limit => TrackStatus::Extrapolating,
+            LossMode::Extrapolate(_) => TrackStatus::Lost,
+        };
+
+        if status != self.status {
+            let hex = self.current.as_deref().unwrap_or("-");
+            match (self.status, status) {
+                (TrackStatus::Extrapolating, _) => println!(
+                    "Tracker: stopped extrapolating {} for {} after {}s",
+                    hex,
+                    self.target,
+                    since.as_secs()
+                ),
+                (_, TrackStatus::Holding) => {
+                    println!("Tracker: lost {} for {}, holding last fix", hex, self.target)
+                }
+                (_, TrackStatus::Extrapolating) => {
+                    println!("Tracker: lost {} for {}, extrapolating ({})", hex, self.target, self.on_loss)
+                }
+                _ => println!("Tracker: lost {} for {}, stopped sending", hex, self.target),
+            }
+            self.status = status;
+        }
+
+        match status {
+            TrackStatus::Holding => Some(fix),
+            TrackStatus::Extrapolating => Some(extrapolate(&fix, since.as_secs_f64())),
+            _ => None,
+        }
+    }
+
+    /// The chosen aircraft's fix and when it was heard, provided it is fresh
+    /// and has a full position and velocity.
+    fn live_fix(&mut self, map: &HashMap<String, Aircraft>) -> Option<(Fix, Instant)> {
+        let selection = self.select(map);
+        let (hex, aircraft) = selection.chosen?;
+
+        if self.current.as_ref() != Some(hex) {
+            if selection.matched.len() > 1 {
+                println!(
+                    "Tracker: following {} for {} ({} aircraft match)",
+                    hex,
+                    self.target,
+                    selection.matched.len()
+                );
+            } else {
+                println!("Tracker: following {} for {}", hex, self.target);
+            }
+            self.current = Some(hex.clone());
+        }
+        if self.lock && self.locked.is_none() {
+            println!("Tracker: locked onto {}", hex);
+            self.locked = Some(hex.clone());
+        }
+
+        if aircraft.last_updated.elapsed() > STALE_AFTER {
+            return None;
+        }
+
+        let fix = Fix {
+            latitude: aircraft.latitude?,
+            longitude: aircraft.longitude?,
+            altitude_ft: aircraft.altitude_ft?,
+            track: aircraft.track?,
+            ground_speed_kt: aircraft.ground_speed_kt?,
+            vertical_rate_fpm: aircraft.vertical_rate_fpm,
+        };
+        self.last_position = Some((fix.latitude, fix.longitude));
+        Some((fix, aircraft.last_updated))
+    }
+}
+
+/// Great-circle distance in nautical miles.
+pub fn distance_nm(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    const EARTH_RADIUS_NM: f64 = 3440.065;
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let dphi = phi2 - phi1;
+    let dlambda = (lon2 - lon1).to_radians();
+    let a = (dphi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (dlambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_NM * a.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::Instant;
+
+    fn aircraft() -> Aircraft {
+        Aircraft {
+            callsign: Some("UAL123".to_string()),
+            registration: Some("N12345".to_string()),
+            type_code: Some("B738".to_string()),
+            squawk: Some("1200".to_string()),
+            latitude: Some(40.0),
+            longitude: Some(-74.0),
+            altitude_ft: Some(35000.0),
+            ground_speed_kt: Some(450.0),
+            track: Some(270.0),
+            vertical_rate_fpm: None,
+            on_ground: None,
+            trail: crate::trail::Trail::default(),
+            updated: crate::FieldTimes::default(),
+            messages: [0; 8],
+            last_updated: Instant::now(),
+        }
+    }
+
+    // --- Parsing ---
+
+    #[test]
+    fn bare_target_is_callsign() {
+        assert_eq!("ual123".parse(), Ok(Target::Callsign(Pattern::Exact("UAL123".to_string()))));
+    }
+
+    #[test]
+    fn prefixed_targets() {
+        assert_eq!("hex:a1b2c3".parse(), Ok(Target::Hex("A1B2C3".to_string())));
+        assert_eq!("reg:n12345".parse(), Ok(Target::Registration(Pattern::Exact("N12345".to_string()))));
+        assert_eq!("squawk:7700".parse(), Ok(Target::Squawk("7700".to_string())));
+        assert_eq!("callsign:DAL1".parse(), Ok(Target::Callsign(Pattern::Exact("DAL1".to_string()))));
+    }
+
+    #[test]
+    fn pattern_targets() {
+        assert_eq!("ual*".parse(), Ok(Target::Callsign(Pattern::Glob("UAL*".to_string()))));
+        assert_eq!("reg:n12?".parse(), Ok(Target::Registration(Pattern::Glob("N12?".to_string()))));
+        let Ok(Target::Callsign(Pattern::Regex(re))) = "/^UAL\\d+$/".parse() else {
+            panic!("expected callsign regex");
+        };
+        assert_eq!(re.as_str(), "^UAL\\d+$");
+        assert!(matches!(
+            "reg:/^N1/".parse(),
+            Ok(Target::Registration(Pattern::Regex(_)))
+        ));
+        assert!("/[unclosed/".parse::<Target>().is_err());
+    }
+
+    #[test]
+    fn regex_may_contain_colon() {
+        let Ok(Target::Callsign(p)) = "/^A:B$/".parse() else {
+            panic!("expected callsign regex");
+        };
+        assert!(p.matches("a:b"));
+    }
+
+    #[test]
+    fn invalid_targets() {
+        assert!("".parse::<Target>().is_err());
+        assert!("hex:".parse::<Target>().is_err());
+        assert!("hex:XYZ123".parse::<Target>().is_err());
+        assert!("hex:A1B2".parse::<Target>().is_err());
+        assert!("squawk:7800".parse::<Target>().is_err());
+        assert!("tail:N1".parse::<Target>().is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in ["UAL123", "hex:A1B2C3", "reg:N12345", "squawk:7700", "UAL*", "reg:/^N1/"] {
+            let target: Target = s.parse().unwrap();
+            assert_eq!(target.to_string(), s);
+        }
+    }
+
+    // --- Matching ---
+
+    #[test]
+    fn matches_each_kind() {
+        let a = aircraft();
+        assert!(Target::Callsign(Pattern::Exact("UAL123".to_string())).matches("A1B2C3", &a));
+        assert!(Target::Hex("a1b2c3".to_string()).matches("A1B2C3", &a));
+        assert!(Target::Registration(Pattern::Exact("N12345".to_string())).matches("A1B2C3", &a));
+        assert!(Target::Squawk("1200".to_string()).matches("A1B2C3", &a));
+    }
+
+    #[test]
+    fn mismatches_each_kind() {
+        let a = aircraft();
+        assert!(!Target::Callsign(Pattern::Exact("DAL1".to_string())).matches("A1B2C3", &a));
+        assert!(!Target::Hex("FFFFFF".to_string()).matches("A1B2C3", &a));
+        assert!(!Target::Registration(Pattern::Exact("N1".to_string())).matches("A1B2C3", &a));
+        assert!(!Target::Squawk("7700".to_string()).matches("A1B2C3", &a));
+    }
+
+    #[test]
+    fn glob_matching() {
+        let p = Pattern::parse("ual*").unwrap();
+        assert!(p.matches("UAL123"));
+        assert!(p.matches("ual"));
+        assert!(!p.matches("DAL123"));
+
+        let p = Pattern::parse("N12?").unwrap();
+        assert!(p.matches("n123"));
+        assert!(!p.matches("N12"));
+        assert!(!p.matches("N1234"));
+
+        let p = Pattern::parse("*7*").unwrap();
+        assert!(p.matches("UAL17"));
+        assert!(!p.matches("UAL12"));
+    }
+
+    #[test]
+    fn glob_matching_stays_fast_with_many_stars() {
+        let glob = "*A".repeat(31);
+        let p = Pattern::parse(&glob).unwrap();
+        assert_eq!(p, Pattern::Glob(glob));
+        assert!(!p.matches("AAAAAAAB"));
+
+        let p = Pattern::parse(&format!("{}B", "*".repeat(63))).unwrap();
+        assert_eq!(p, Pattern::Glob("*B".to_string()));
+        assert!(p.matches("AAAAAAAB"));
+        assert!(!p.matches("AAAAAAAA"));
+
+        assert!(Pattern::parse(&"*".repeat(100)).is_err());
+        assert!(Pattern::parse(&format!("/{}/", "a".repeat(100))).is_err());
+    }
+
+    #[test]
+    fn glob_backtracks_to_last_star() {
+        let p = Pattern::parse("*AB*C").unwrap();
+        assert!(p.matches("XABYABZC"));
+        assert!(p.matches("ABC"));
+        assert!(!p.matches("ACB"));
+        assert!(Pattern::parse("U?L*3").unwrap().matches("UAL123"));
+        assert!(!Pattern::parse("U?L*3").unwrap().matches("UAL124"));
+    }
+
+    #[test]
+    fn regex_matching_ignores_case() {
+        let p = Pattern::parse("/^ual\\d{3}$/").unwrap();
+        assert!(p.matches("UAL123"));
+        assert!(!p.matches("UAL12"));
+    }
+
+    #[test]
+    fn exactness() {
+        assert!("UAL123".parse::<Target>().unwrap().is_exact());
+        assert!("hex:A1B2C3".parse::<Target>().unwrap().is_exact());
+        assert!(!"UAL*".parse::<Target>().unwrap().is_exact());
+        assert!(!"reg:/^N/".parse::<Target>().unwrap().is_exact());
+    }
+
+    #[test]
+    fn missing_fields_never_match() {
+        let mut a = aircraft();
+        a.callsign = None;
+        a.registration = None;
+        a.squawk = None;
+        assert!(!Target::Callsign(Pattern::Exact("UAL123".to_string())).matches("A1B2C3", &a));
+        assert!(!Target::Registration(Pattern::Exact("N12345".to_string())).matches("A1B2C3", &a));
+        assert!(!Target::Squawk("1200".to_string()).matches("A1B2C3", &a));
+    }
+
+    // --- Selection ---
+
+    fn at(lat: f64, lon: f64, age_secs: u64) -> Aircraft {
+        let mut a = aircraft();
+        a.latitude = Some(lat);
+        a.longitude = Some(lon);
+        a.last_updated = Instant::now() - Duration::from_secs(age_secs);
+        a
+    }
+
+    fn tracker(target: &str) -> Tracker {
+        Tracker::new(target.parse().unwrap())
+    }
+
+    #[test]
+    fn update_by_hex_without_callsign() {
+        let mut a = aircraft();
+        a.callsign = None;
+        let map = HashMap::from([("A1B2C3".to_string(), a)]);
+        let fix = tracker("hex:A1B2C3").update(&map).unwrap();
+        assert_eq!(fix.latitude, 40.0);
+        assert_eq!(fix.ground_speed_kt, 450.0);
+    }
+
+    #[test]
+    fn update_requires_position() {
+        let mut a = aircraft();
+        a.latitude = None;
+        let map = HashMap::from([("A1B2C3".to_string(), a)]);
+        assert!(tracker("hex:A1B2C3").update(&map).is_none());
+    }
+
+    #[test]
+    fn update_stale_aircraft_gives_no_fix() {
+        let map = HashMap::from([("A1B2C3".to_string(), at(40.0, -74.0, 10))]);
+        let mut t = tracker("UAL123");
+        assert!(t.update(&map).is_none());
+        assert_eq!(t.current.as_deref(), Some("A1B2C3"));
+    }
+
+    #[test]
+    fn single_match_is_not_a_conflict() {
+        let map = HashMap::from([("A1B2C3".to_string(), aircraft())]);
+        let t = tracker("UAL123");
+        let sel = t.select(&map);
+        assert_eq!(sel.chosen.unwrap().0, "A1B2C3");
+        assert!(!sel.is_conflict());
+    }
+
+    #[test]
+    fn conflict_prefers_freshest() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at(40.0, -74.0, 3)),
+            ("BBBBBB".to_string(), at(41.0, -75.0, 0)),
+        ]);
+        let t = tracker("UAL123");
+        let sel = t.select(&map);
+        assert!(sel.is_conflict());
+        assert_eq!(sel.matched, vec!["AAAAAA", "BBBBBB"]);
+        assert_eq!(sel.chosen.unwrap().0, "BBBBBB");
+    }
+
+    #[test]
+    fn conflict_prefers_current_while_fresh() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at(40.0, -74.0, 3)),
+            ("BBBBBB".to_string(), at(41.0, -75.0, 0)),
+        ]);
+        let mut t = tracker("UAL123");
+        t.current = Some("AAAAAA".to_string());
+        assert_eq!(t.select(&map).chosen.unwrap().0, "AAAAAA");
+    }
+
+    #[test]
+    fn conflict_drops_current_once_stale() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at(40.0, -74.0, 8)),
+            ("BBBBBB".to_string(), at(41.0, -75.0, 0)),
+        ]);
+        let mut t = tracker("UAL123");
+        t.current = Some("AAAAAA".to_string());
+        assert_eq!(t.select(&map).chosen.unwrap().0, "BBBBBB");
+    }
+
+    #[test]
+    fn conflict_tie_goes_to_closest_to_last_position() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at(45.0, -70.0, 0)),
+            ("BBBBBB".to_string(), at(40.1, -74.1, 0)),
+        ]);
+        let mut t = tracker("UAL123");
+        t.last_position = Some((40.0, -74.0));
+        assert_eq!(t.select(&map).chosen.unwrap().0, "BBBBBB");
+    }
+
+    #[test]
+    fn conflict_without_history_is_deterministic() {
+        let map = HashMap::from([
+            ("BBBBBB".to_string(), at(41.0, -75.0, 0)),
+            ("AAAAAA".to_string(), at(40.0, -74.0, 0)),
+        ]);
+        for _ in 0..10 {
+            assert_eq!(tracker("UAL123").select(&map).chosen.unwrap().0, "AAAAAA");
+        }
+    }
+
+    #[test]
+    fn update_remembers_choice() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at(40.0, -74.0, 0)),
+            ("BBBBBB".to_string(), at(41.0, -75.0, 0)),
+        ]);
+        let mut t = tracker("UAL123");
+        t.update(&map).unwrap();
+        assert_eq!(t.current.as_deref(), Some("AAAAAA"));
+        assert_eq!(t.last_position, Some((40.0, -74.0)));
+    }
+
+    // --- Selection rules ---
+
+    fn at_alt(lat: f64, lon: f64, alt: f64, age_secs: u64) -> Aircraft {
+        let mut a = at(lat, lon, age_secs);
+        a.altitude_ft = Some(alt);
+        a
+    }
+
+    #[test]
+    fn pattern_matches_are_not_a_conflict() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at(40.0, -74.0, 0)),
+            ("BBBBBB".to_string(), at(41.0, -75.0, 0)),
+        ]);
+        let sel = tracker("UAL*").select(&map);
+        assert_eq!(sel.matched.len(), 2);
+        assert!(!sel.is_conflict());
+    }
+
+    #[test]
+    fn nearest_rule_uses_receiver() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at(42.0, -74.0, 0)),
+            ("BBBBBB".to_string(), at(40.2, -74.0, 3)),
+        ]);
+        let mut t = tracker("UAL*");
+        t.rule = SelectionRule::Nearest;
+        t.receiver = Some((40.0, -74.0));
+        assert_eq!(t.select(&map).chosen.unwrap().0, "BBBBBB");
+    }
+
+    #[test]
+    fn lowest_rule_uses_altitude() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at_alt(40.0, -74.0, 2000.0, 3)),
+            ("BBBBBB".to_string(), at_alt(40.0, -74.0, 35000.0, 0)),
+        ]);
+        let mut t = tracker("UAL*");
+        t.rule = SelectionRule::Lowest;
+        assert_eq!(t.select(&map).chosen.unwrap().0, "AAAAAA");
+    }
+
+    #[test]
+    fn rule_does_not_override_current() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at_alt(40.0, -74.0, 2000.0, 0)),
+            ("BBBBBB".to_string(), at_alt(40.0, -74.0, 35000.0, 0)),
+        ]);
+        let mut t = tracker("UAL*");
+        t.rule = SelectionRule::Lowest;
+        t.current = Some("BBBBBB".to_string());
+        assert_eq!(t.select(&map).chosen.unwrap().0, "BBBBBB");
+    }
+
+    #[test]
+    fn selection_rule_from_str() {
+        assert_eq!("Nearest".parse(), Ok(SelectionRule::Nearest));
+        assert_eq!("lowest".parse(), Ok(SelectionRule::Lowest));
+        assert_eq!("freshest".parse(), Ok(SelectionRule::Freshest));
+        assert!("random".parse::<SelectionRule>().is_err());
+    }
+
+    #[test]
+    fn position_parsing() {
+        assert_eq!(parse_position("40.64, -73.78"), Ok((40.64, -73.78)));
+        assert!(parse_position("40.64").is_err());
+        assert!(parse_position("91,0").is_err());
+        assert!(parse_position("north,east").is_err());
+    }
+
+    // --- Nearest to a point ---
+
+    #[test]
+    fn near_target_parsing() {
+        assert_eq!(
+            "near:40.64,-73.78".parse(),
+            Ok(Target::Nearest {
+                lat: 40.64,
+                lon: -73.78,
+                band: None
+            })
+        );
+        assert_eq!(
+            "near:40.64,-73.78,0-5000".parse(),
+            Ok(Target::Nearest {
+                lat: 40.64,
+                lon: -73.78,
+                band: Some((0.0, 5000.0))
+            })
+        );
+        assert!("near:40.64".parse::<Target>().is_err());
+        assert!("near:40.64,-73.78,5000".parse::<Target>().is_err());
+        assert!("near:40.64,-73.78,5000-1000".parse::<Target>().is_err());
+    }
+
+    #[test]
+    fn near_target_display_round_trips() {
+        for s in ["near:40.64,-73.78", "near:40.64,-73.78,0-5000"] {
+            assert_eq!(s.parse::<Target>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn near_target_altitude_band() {
+        let target: Target = "near:40,-74,1000-5000".parse().unwrap();
+        assert!(target.matches("AAAAAA", &at_alt(40.0, -74.0, 3000.0, 0)));
+        assert!(!target.matches("AAAAAA", &at_alt(40.0, -74.0, 35000.0, 0)));
+        let mut no_alt = at(40.0, -74.0, 0);
+        no_alt.altitude_ft = None;
+        assert!(!target.matches("AAAAAA", &no_alt));
+        assert!(!target.is_exact());
+    }
+
+    #[test]
+    fn near_target_picks_closest() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at(40.5, -74.0, 0)),
+            ("BBBBBB".to_string(), at(40.1, -74.0, 0)),
+        ]);
+        let t = tracker("near:40,-74");
+        assert_eq!(t.select(&map).chosen.unwrap().0, "BBBBBB");
+    }
+
+    #[test]
+    fn near_target_ignores_stale_aircraft() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at(40.5, -74.0, 0)),
+            ("BBBBBB".to_string(), at(40.0, -74.0, 10)),
+        ]);
+        let t = tracker("near:40,-74");
+        assert_eq!(t.select(&map).chosen.unwrap().0, "AAAAAA");
+    }
+
+    #[test]
+    fn near_target_hysteresis_keeps_current() {
+        // BBBBBB is 0.6 nm closer, inside the 1 nm hysteresis
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at(40.1, -74.0, 0)),
+            ("BBBBBB".to_string(), at(40.09, -74.0, 0)),
+        ]);
+        let mut t = tracker("near:40,-74");
+        t.current = Some("AAAAAA".to_string());
+        assert_eq!(t.select(&map).chosen.unwrap().0, "AAAAAA");
+    }
+
+    #[test]
+    fn near_target_switches_when_clearly_closer() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at(40.5, -74.0, 0)),
+            ("BBBBBB".to_string(), at(40.1, -74.0, 0)),
+        ]);
+        let mut t = tracker("near:40,-74");
+        t.current = Some("AAAAAA".to_string());
+        assert_eq!(t.select(&map).chosen.unwrap().0, "BBBBBB");
+    }
+
+    #[test]
+    fn near_target_falls_back_when_current_goes_stale() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at(40.1, -74.0, 10)),
+            ("BBBBBB".to_string(), at(40.5, -74.0, 0)),
+        ]);
+        let mut t = tracker("near:40,-74");
+        t.current = Some("AAAAAA".to_string());
+        assert_eq!(t.select(&map).chosen.unwrap().0, "BBBBBB");
+    }
+
+    // --- Loss of track ---
+
+    fn north_fix() -> Fix {
+        Fix {
+            latitude: 40.0,
+            longitude: -74.0,
+            altitude_ft: 5000.0,
+            track: 0.0,
+            ground_speed_kt: 60.0,
+            vertical_rate_fpm: Some(-500.0),
+        }
+    }
+
+    #[test]
+    fn loss_mode_parsing() {
+        assert_eq!("stop".parse(), Ok(LossMode::Stop));
+        assert_eq!("HOLD".parse(), Ok(LossMode::Hold));
+        assert_eq!("extrapolate".parse(), Ok(LossMode::Extrapolate(DEFAULT_EXTRAPOLATE)));
+        assert_eq!(
+            "extrapolate:10".parse(),
+            Ok(LossMode::Extrapolate(Duration::from_secs(10)))
+        );
+        assert!("extrapolate:soon".parse::<LossMode>().is_err());
+        assert!("hold:5".parse::<LossMode>().is_err());
+        assert!("fade".parse::<LossMode>().is_err());
+        assert_eq!(LossMode::Extrapolate(Duration::from_secs(10)).to_string(), "extrapolate:10");
+    }
+
+    #[test]
+    fn extrapolate_along_track() {
+        let fix = extrapolate(&north_fix(), 60.0);
+        assert!((fix.latitude - (40.0 + 1.0 / 60.0)).abs() < 1e-4);
+        assert!((fix.longitude + 74.0).abs() < 1e-9);
+        assert!((fix.altitude_ft - 4500.0).abs() < 1e-9);
+        assert_eq!(fix.track, 0.0);
+    }
+
+    #[test]
+    fn extrapolate_east_wraps_longitude() {
+        let mut fix = north_fix();
+        fix.longitude = 179.99;
+        fix.latitude = 0.0;
+        fix.track = 90.0;
+        fix.ground_speed_kt = 600.0;
+        assert!(extrapolate(&fix, 60.0).longitude < -179.0);
+    }
+
+    #[test]
+    fn stop_mode_sends_nothing_once_stale() {
+        let mut t = tracker("UAL123");
+        let fresh = HashMap::from([("AAAAAA".to_string(), at(40.0, -74.0, 0))]);
+        assert!(t.update(&fresh).is_some());
+        assert_eq!(t.status, TrackStatus::Tracking);
+
+        let stale = HashMap::from([("AAAAAA".to_string(), at(40.0, -74.0, 10))]);
+        assert!(t.update(&stale).is_none());
+        assert_eq!(t.status, TrackStatus::Lost);
+        assert!(t.lost_for().is_some());
+    }
+
+    #[test]
+    fn no_fix_yet_stays_acquiring() {
+        let mut t = tracker("UAL123");
+        t.on_loss = LossMode::Hold;
+        assert!(t.update(&HashMap::new()).is_none());
+        assert_eq!(t.status, TrackStatus::Acquiring);
+        assert!(t.lost_for().is_none());
+    }
+
+    #[test]
+    fn hold_mode_repeats_last_fix() {
+        let mut t = tracker("UAL123");
+        t.on_loss = LossMode::Hold;
+        let fresh = HashMap::from([("AAAAAA".to_string(), at(40.0, -74.0, 0))]);
+        t.update(&fresh);
+
+        let fix = t.update(&HashMap::new()).unwrap();
+        assert_eq!(t.status, TrackStatus::Holding);
+        assert_eq!((fix.latitude, fix.longitude), (40.0, -74.0));
+    }
+
+    #[test]
+    fn extrapolate_mode_stops_after_limit() {
+        let mut t = tracker("UAL123");
+        t.on_loss = LossMode::Extrapolate(Duration::from_secs(30));
+        let heard = Instant::now();
+        t.last_fix = Some((north_fix(), heard));
+        t.status = TrackStatus::Tracking;
+
+        let fix = t.coast(heard + Duration::from_secs(10)).unwrap();
+        assert_eq!(t.status, TrackStatus::Extrapolating);
+        assert!(fix.latitude > 40.0);
+
+        assert!(t.coast(heard + Duration::from_secs(31)).is_none());
+        assert_eq!(t.status, TrackStatus::Lost);
+    }
+
+    #[test]
+    fn track_recovers_when_aircraft_returns() {
+        let mut t = tracker("UAL123");
+        t.on_loss = LossMode::Hold;
+        let fresh = HashMap::from([("AAAAAA".to_string(), at(40.0, -74.0, 0))]);
+        t.update(&fresh);
+        t.update(&HashMap::new());
+        assert_eq!(t.status, TrackStatus::Holding);
+
+        let moved = HashMap::from([("AAAAAA".to_string(), at(40.5, -74.0, 0))]);
+        assert_eq!(t.update(&moved).unwrap().latitude, 40.5);
+        assert_eq!(t.status, TrackStatus::Tracking);
+    }
+
+    #[test]
+    fn set_target_forgets_last_fix() {
+        let mut t = tracker("UAL123");
+        t.on_loss = LossMode::Hold;
+        let fresh = HashMap::from([("AAAAAA".to_string(), at(40.0, -74.0, 0))]);
+        t.update(&fresh);
+        t.set_target("DAL1".parse().unwrap());
+        assert!(t.update(&fresh).is_none());
+        assert_eq!(t.status, TrackStatus::Acquiring);
+    }
+
+    #[test]
+    fn stopped_tracker_sends_nothing_until_retargeted() {
+        let mut t = tracker("UAL123");
+        t.on_loss = LossMode::Hold;
+        let fresh = HashMap::from([("AAAAAA".to_string(), at(40.0, -74.0, 0))]);
+        assert!(t.update(&fresh).is_some());
+
+        t.stop();
+        assert!(t.update(&fresh).is_none());
+        assert_eq!(t.status, TrackStatus::Stopped);
+
+        t.set_target("UAL123".parse().unwrap());
+        assert!(t.update(&fresh).is_some());
+        assert_eq!(t.status, TrackStatus::Tracking);
+    }
+
+    // --- Watch list ---
+
+    fn on_ground(alt: f64, ground: bool) -> Aircraft {
+        let mut a = aircraft();
+        a.altitude_ft = Some(alt);
+        a.on_ground = Some(ground);
+        a
+    }
+
+    fn watching(target: &str, until: &str, next: &str) -> Tracker {
+        let mut t = tracker(target);
+        t.until = parse_conditions(until).unwrap();
+        t.watch.push_back(next.parse().unwrap());
+        t
+    }
+
+    #[test]
+    fn switch_condition_parsing() {
+        assert_eq!("landed".parse(), Ok(SwitchCondition::Landed));
+        assert_eq!(
+            "stale:10".parse(),
+            Ok(SwitchCondition::Stale(Duration::from_secs(600)))
+        );
+        assert_eq!("below:1000".parse(), Ok(SwitchCondition::Below(1000.0)));
+        assert!("below".parse::<SwitchCondition>().is_err());
+        assert!("stale:soon".parse::<SwitchCondition>().is_err());
+        assert!("airborne".parse::<SwitchCondition>().is_err());
+        assert_eq!(parse_conditions(" landed, below:500").unwrap().len(), 2);
+        assert!(parse_conditions("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn watch_entry_round_trips() {
+        for s in ["DAL456", "DAL456 until landed", "hex:A1B2C3 until stale:5,below:1000"] {
+            assert_eq!(s.parse::<WatchEntry>().unwrap().to_string(), s);
+        }
+        let entry: WatchEntry = "/^DAL 1/ until landed".parse().unwrap();
+        assert_eq!(entry.until, vec![SwitchCondition::Landed]);
+        assert!("DAL456 until sunset".parse::<WatchEntry>().is_err());
+    }
+
+    #[test]
+    fn switches_after_landing() {
+        let mut t = watching("UAL123", "landed", "DAL456 until below:1000");
+        let airborne = HashMap::from([("AAAAAA".to_string(), on_ground(3000.0, false))]);
+        t.update(&airborne);
+        t.update(&airborne);
+        assert_eq!(t.target.to_string(), "UAL123");
+
+        let landed = HashMap::from([("AAAAAA".to_string(), on_ground(0.0, true))]);
+        t.update(&landed);
+        assert_eq!(t.target.to_string(), "DAL456");
+        assert_eq!(t.until, vec![SwitchCondition::Below(1000.0)]);
+        assert!(t.watch.is_empty());
+    }
+
+    #[test]
+    fn on_ground_before_departure_does_not_switch() {
+        let mut t = watching("UAL123", "landed", "DAL456");
+        let at_gate = HashMap::from([("AAAAAA".to_string(), on_ground(0.0, true))]);
+        t.update(&at_gate);
+        t.update(&at_gate);
+        assert_eq!(t.target.to_string(), "UAL123");
+    }
+
+    #[test]
+    fn switches_when_descending_below_altitude() {
+        let mut t = watching("UAL123", "below:1000", "DAL456");
+        let low = HashMap::from([("AAAAAA".to_string(), on_ground(800.0, false))]);
+        t.update(&low);
+        t.update(&low);
+        assert_eq!(t.target.to_string(), "UAL123");
+
+        let high = HashMap::from([("AAAAAA".to_string(), on_ground(5000.0, false))]);
+        t.update(&high);
+        t.update(&low);
+        assert_eq!(t.target.to_string(), "DAL456");
+    }
+
+    #[test]
+    fn switches_when_stale() {
+        let mut t = watching("UAL123", "stale:1", "DAL456");
+        let map = HashMap::from([("AAAAAA".to_string(), at(40.0, -74.0, 0))]);
+        t.update(&map);
+        let now = Instant::now();
+        assert!(t.switch_reason(&map, now).is_none());
+        assert_eq!(t.switch_reason(&map, now + Duration::from_secs(61)).as_deref(), Some("stale for 1 min"));
+    }
+
+    #[test]
+    fn conditions_without_watch_list_keep_target() {
+        let mut t = tracker("UAL123");
+        t.until = vec![SwitchCondition::Below(1000.0)];
+        t.update(&HashMap::from([("AAAAAA".to_string(), on_ground(5000.0, false))]));
+        t.update(&HashMap::from([("AAAAAA".to_string(), on_ground(500.0, false))]));
+        assert_eq!(t.target.to_string(), "UAL123");
+    }
+
+    // --- Slots ---
+
+    fn slots(names: &[&str]) -> Vec<Slot> {
+        names
+            .iter()
+            .map(|name| Slot {
+                name: name.to_string(),
+                tracker: Arc::new(RwLock::new(tracker("TEST"))),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn slot_index_defaults_to_primary() {
+        let slots = slots(&["main", "lab2"]);
+        assert_eq!(slot_index(&slots, None), Some(0));
+        assert_eq!(slot_index(&slots, Some("lab2")), Some(1));
+        assert_eq!(slot_index(&slots, Some("lab3")), None);
+        assert_eq!(slot_index(&[], None), None);
+    }
+
+    #[test]
+    fn parse_slot_spec() {
+        let (name, target) = parse_slot("lab2=hex:a1b2c3").unwrap();
+        assert_eq!(name, "lab2");
+        assert_eq!(target, Target::Hex("A1B2C3".to_string()));
+        assert!(parse_slot("UAL123").is_err());
+        assert!(parse_slot("=UAL123").is_err());
+        assert!(parse_slot("lab 2=UAL123").is_err());
+    }
+
+    // --- Lock ---
+
+    #[test]
+    fn lock_pins_first_acquisition() {
+        let mut map = HashMap::from([("AAAAAA".to_string(), at(40.0, -74.0, 0))]);
+        let mut t = tracker("UAL123");
+        t.lock();
+        assert!(t.locked.is_none());
+        t.update(&map).unwrap();
+        assert_eq!(t.locked.as_deref(), Some("AAAAAA"));
+
+        // Callsign changes mid-flight, the lock keeps following the airframe
+        map.get_mut("AAAAAA").unwrap().callsign = Some("UAL9".to_string());
+        let fix = t.update(&map).unwrap();
+        assert_eq!(fix.latitude, 40.0);
+        assert!(t.select(&map).matched.is_empty());
+    }
+
+    #[test]
+    fn lock_ignores_fresher_match() {
+        let map = HashMap::from([
+            ("AAAAAA".to_string(), at(40.0, -74.0, 8)),
+            ("BBBBBB".to_string(), at(41.0, -75.0, 0)),
+        ]);
+        let mut t = tracker("UAL123");
+        t.current = Some("AAAAAA".to_string());
+        t.lock();
+        assert_eq!(t.select(&map).chosen.unwrap().0, "AAAAAA");
+        // Stale locked aircraft gives no fix rather than a switch
+        assert!(t.update(&map).is_none());
+        assert_eq!(t.current.as_deref(), Some("AAAAAA"));
+    }
+
+    #[test]
+    fn release_resumes_matching() {
+        let mut map = HashMap::from([("AAAAAA".to_string(), at(40.0, -74.0, 0))]);
+        let mut t = tracker("UAL123");
+        t.lock();
+        t.update(&map).unwrap();
+        map.get_mut("AAAAAA").unwrap().callsign = Some("UAL9".to_string());
+
+        t.release();
+        assert!(!t.lock);
+        assert!(t.locked.is_none());
+        assert!(t.update(&map).is_none());
+    }
+
+    #[test]
+    fn set_target_keeps_lock_armed() {
+        let mut t = tracker("UAL123");
+        t.current = Some("AAAAAA".to_string());
+        t.lock();
+        t.set_target("DAL1".parse().unwrap());
+        assert!(t.lock);
+        assert!(t.locked.is_none());
+    }
+
+    #[test]
+    fn set_target_resets_state() {
+        let mut t = tracker("UAL123");
+        t.current = Some("AAAAAA".to_string());
+        t.last_position = Some((40.0, -74.0));
+        t.set_target("DAL1".parse().unwrap());
+        assert!(t.current.is_none());
+        assert!(t.last_position.is_none());
+    }
+
+    #[test]
+    fn distance_one_degree_of_latitude() {
+        assert!((distance_nm(40.0, -74.0, 41.0, -74.0) - 60.0).abs() < 0.1);
+        assert_eq!(distance_nm(40.0, -74.0, 40.0, -74.0), 0.0);
+    }
+}
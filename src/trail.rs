@@ -0,0 +1,78 @@
+use std::collections::VecDeque;
+use tokio::time::Instant;
+
+/// Positions kept per aircraft; at one position a second, ten minutes.
+pub const TRAIL_LEN: usize = 600;
+
+/// One reported position, with the altitude and speed known when it arrived.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrailPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude_ft: Option<f64>,
+    pub ground_speed_kt: Option<f64>,
+    pub at: Instant,
+}
+
+/// Bounded history of an aircraft's positions, oldest first.
+#[derive(Clone, Debug, Default)]
+pub struct Trail {
+    points: VecDeque<TrailPoint>,
+}
+
+impl Trail {
+    /// Append a position, dropping the oldest once full. Repeats of the
+    /// last position and altitude are skipped.
+    pub fn push(&mut self, point: TrailPoint) {
+        if let Some(last) = self.points.back() {
+            if last.latitude == point.latitude
+                && last.longitude == point.longitude
+                && last.altitude_ft == point.altitude_ft
+            {
+                return;
+            }
+        }
+        if self.points.len() == TRAIL_LEN {
+            self.points.pop_front();
+        }
+        self.points.push_back(point);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TrailPoint> {
+        self.points.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(lat: f64, alt: f64) -> TrailPoint {
+        TrailPoint {
+            latitude: lat,
+            longitude: -74.0,
+            altitude_ft: Some(alt),
+            ground_speed_kt: None,
+            at: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn keeps_newest_points() {
+        let mut trail = Trail::default();
+        for i in 0..TRAIL_LEN + 10 {
+            trail.push(point(i as f64 * 0.001, 5000.0));
+        }
+        assert_eq!(trail.iter().count(), TRAIL_LEN);
+        assert_eq!(trail.iter().next().unwrap().latitude, 10.0 * 0.001);
+    }
+
+    #[test]
+    fn skips_repeated_position() {
+        let mut trail = Trail::default();
+        trail.push(point(40.0, 5000.0));
+        trail.push(point(40.0, 5000.0));
+        trail.push(point(40.0, 5100.0));
+        assert_eq!(trail.iter().count(), 2);
+    }
+}
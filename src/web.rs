@@ -1,38 +1,271 @@
-use crate::{AircraftMap, TrackedCallsign};
-use axum::extract::State;
+mod api;
+mod auth;
+mod detail;
+mod events;
+mod geojson;
+mod kml;
+mod recordings;
+mod tls;
+
+use crate::tracker::{self, Selection, Slot, Slots, Target, TrackStatus, Tracker, WatchEntry};
+use crate::xgps::{Destination, Destinations, MainFix};
+use crate::{Aircraft, AircraftMap};
+use api::ApiError;
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::middleware;
 use axum::response::{Html, Json, Redirect};
 use axum::routing::{get, post};
 use axum::{Form, Router};
+use futures_util::future::{self, BoxFuture, FutureExt};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::future::IntoFuture;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
 use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{watch, Notify};
+
+pub use auth::{parse_login, parse_token, Auth, Login};
+pub use tls::{self_signed_names, Tls, TlsFiles};
 
 struct AppState {
     aircraft_map: AircraftMap,
-    tracked_callsign: TrackedCallsign,
+    slots: Slots,
+    destinations: Destinations,
+    /// Receiver position, centre of the map's range rings
+    receiver: Option<(f64, f64)>,
+    /// Directory recordings are written to, when recording
+    recordings: Option<PathBuf>,
+    /// Credentials needed to change anything, if any
+    auth: Option<Auth>,
+    /// Token every form posts back, fresh each run
+    csrf: String,
+    /// Served over TLS, so links back to us use https
+    https: bool,
+    /// Latest state for the event stream, computed once for every connection
+    snapshots: watch::Sender<Option<Arc<DataResponse>>>,
+    /// Woken after a change made through the web, to publish it straight away
+    refresh: Notify,
 }
 
 #[derive(Deserialize)]
 struct TrackForm {
-    callsign: String,
+    /// Target in the CLI syntax; `callsign` is accepted for older clients
+    #[serde(alias = "callsign")]
+    target: String,
+    /// Slot to assign the target to, the primary slot if absent
+    slot: Option<String>,
+    /// Switch conditions ending the target, as in `--until`
+    #[serde(default)]
+    until: String,
+}
+
+#[derive(Deserialize)]
+struct WatchForm {
+    slot: Option<String>,
+    target: String,
+    #[serde(default)]
+    until: String,
+}
+
+#[derive(Deserialize)]
+struct WatchIndexForm {
+    slot: Option<String>,
+    index: usize,
+}
+
+#[derive(Deserialize)]
+struct SlotQuery {
+    slot: Option<String>,
+}
+
+/// Narrowing and ordering of the aircraft `/data` returns, matching the
+/// page's table controls.
+#[derive(Debug, Default, Deserialize)]
+struct DataQuery {
+    /// Column to sort by, one of `SORT_COLUMNS`; a leading `-` sorts descending
+    sort: Option<String>,
+    /// Case-insensitive text in the hex, callsign or squawk
+    filter: Option<String>,
+    /// Leave out aircraft without a position
+    #[serde(default)]
+    positioned: bool,
+    /// Leave out aircraft not heard from for longer, in seconds
+    max_age: Option<u64>,
+}
+
+const SORT_COLUMNS: &[&str] = &[
+    "hex", "callsign", "registration", "squawk", "lat", "lon", "alt_ft", "gs_kt", "track", "age",
+];
+
+#[derive(PartialEq, PartialOrd)]
+enum SortValue<'a> {
+    Text(&'a str),
+    Number(f64),
+}
+
+/// Value of a table column, `None` when the aircraft hasn't reported it.
+fn sort_value<'a>(entry: &'a AircraftEntry, column: &str) -> Option<SortValue<'a>> {
+    let text = |s: Option<&'a str>| s.filter(|s| !s.is_empty()).map(SortValue::Text);
+    match column {
+        "hex" => text(Some(&entry.hex)),
+        "callsign" => text(Some(&entry.callsign)),
+        "registration" => text(entry.registration.as_deref()),
+        "squawk" => text(entry.squawk.as_deref()),
+        "lat" => entry.lat.map(SortValue::Number),
+        "lon" => entry.lon.map(SortValue::Number),
+        "alt_ft" => entry.alt_ft.map(SortValue::Number),
+        "gs_kt" => entry.gs_kt.map(SortValue::Number),
+        "track" => entry.track.map(SortValue::Number),
+        "age" => Some(SortValue::Number(entry.age as f64)),
+        _ => None,
+    }
+}
+
+impl DataQuery {
+    /// Filter and sort `entries` in place. Missing values sort last in
+    /// either direction, ties by hex.
+    fn apply(&self, entries: &mut Vec<AircraftEntry>) -> Result<(), String> {
+        if let Some(filter) = self.filter.as_deref().map(str::trim).filter(|f| !f.is_empty()) {
+            let filter = filter.to_ascii_lowercase();
+            entries.retain(|e| {
+                [Some(e.hex.as_str()), Some(e.callsign.as_str()), e.squawk.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .any(|field| field.to_ascii_lowercase().contains(&filter))
+            });
+        }
+        if self.positioned {
+            entries.retain(|e| e.lat.is_some() && e.lon.is_some());
+        }
+        if let Some(max_age) = self.max_age {
+            entries.retain(|e| e.age <= max_age);
+        }
+
+        let Some(sort) = self.sort.as_deref() else {
+            return Ok(());
+        };
+        let (column, descending) = match sort.strip_prefix('-') {
+            Some(column) => (column, true),
+            None => (sort, false),
+        };
+        if !SORT_COLUMNS.contains(&column) {
+            return Err(format!(
+                "unknown sort column '{}' (expected one of {})",
+                column,
+                SORT_COLUMNS.join(", ")
+            ));
+        }
+        entries.sort_by(|a, b| {
+            let order = match (sort_value(a, column), sort_value(b, column)) {
+                (Some(x), Some(y)) => {
+                    let order = x.partial_cmp(&y).unwrap_or(Ordering::Equal);
+                    if descending {
+                        order.reverse()
+                    } else {
+                        order
+                    }
+                }
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            order.then_with(|| a.hex.cmp(&b.hex))
+        });
+        Ok(())
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 struct DataResponse {
-    tracked: String,
+    /// Receiver position as `[lat, lon]`, when configured
+    receiver: Option<(f64, f64)>,
+    /// The main slot's fields at the top level, where `tracked`, `chosen`,
+    /// `lock` and the rest were before there were slots
+    #[serde(flatten)]
+    main: Option<SlotEntry>,
+    slots: Vec<SlotEntry>,
     aircraft: Vec<AircraftEntry>,
+    destinations: Vec<DestinationEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct SlotEntry {
+    name: String,
+    tracked: String,
+    /// Hex of every aircraft matching the target when more than one does
+    conflict: Vec<String>,
+    /// Hex of every aircraft matching the target
+    matched: Vec<String>,
+    /// Hex of the aircraft chosen among them
+    chosen: Option<String>,
+    rule: String,
+    lock: bool,
+    locked: Option<String>,
+    /// acquiring, tracking, holding, extrapolating or lost
+    status: String,
+    /// Seconds since the last good fix while the track is lost
+    lost_secs: Option<u64>,
+    /// Conditions ending the current target
+    until: Vec<String>,
+    /// Targets queued after it, in order
+    watch: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 struct AircraftEntry {
     hex: String,
     callsign: String,
+    registration: Option<String>,
+    type_code: Option<String>,
+    squawk: Option<String>,
     lat: Option<f64>,
     lon: Option<f64>,
     alt_ft: Option<f64>,
     gs_kt: Option<f64>,
     track: Option<f64>,
+    vs_fpm: Option<f64>,
+    on_ground: Option<bool>,
     age: u64,
     tracking: bool,
+    matched: bool,
+    /// Names of the slots this aircraft feeds
+    slots: Vec<String>,
+    /// When it was last heard, so the event stream can tell a message
+    /// that changed nothing from silence
+    #[serde(skip)]
+    heard: Option<tokio::time::Instant>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct DestinationEntry {
+    addr: String,
+    kind: String,
+    variant: String,
+    slot: String,
+    sent: u64,
+    errors: u64,
+    source: String,
+}
+
+/// One slot's selection, read under its tracker lock.
+struct SlotView<'a> {
+    entry: SlotEntry,
+    selection: Selection<'a>,
+}
+
+impl SlotView<'_> {
+    fn feeds(&self, hex: &str) -> bool {
+        self.selection.chosen.is_some_and(|(h, _)| h == hex)
+    }
+
+    fn matches(&self, hex: &str) -> bool {
+        self.selection.matched.iter().any(|h| *h == hex)
+    }
 }
 
 fn app(state: Arc<AppState>) -> Router {
@@ -40,72 +273,500 @@ fn app(state: Arc<AppState>) -> Router {
         .route("/", get(get_index))
         .route("/data", get(get_data))
         .route("/track", post(post_track))
+        .route("/lock", post(post_lock))
+        .route("/release", post(post_release))
+        .route("/watch", post(post_watch))
+        .route("/watch/remove", post(post_watch_remove))
+        .route("/watch/up", post(post_watch_up))
+        .route("/watch/next", post(post_watch_next))
+        .merge(api::routes())
+        .merge(detail::routes())
+        .merge(events::routes())
+        .merge(geojson::routes())
+        .merge(kml::routes())
+        .merge(recordings::routes())
+        .layer(middleware::from_fn_with_state(state.clone(), events::refresh_after_change))
+        .layer(middleware::from_fn_with_state(state.clone(), auth::check_csrf))
+        .layer(middleware::from_fn_with_state(state.clone(), auth::require_login))
         .with_state(state)
 }
 
-pub async fn run(aircraft_map: AircraftMap, tracked_callsign: TrackedCallsign) {
+/// Default address of the web interface.
+pub const DEFAULT_BIND: &str = "0.0.0.0:8081";
+
+/// A web bind address as given, with the socket addresses it stands for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebBind {
+    pub spec: String,
+    pub addrs: Vec<SocketAddr>,
+}
+
+/// Parse `HOST:PORT`, `[IPV6]:PORT` or a bare `PORT` (all IPv4 interfaces).
+/// Hostnames bind every address they resolve to, so `localhost:PORT`
+/// serves this machine only, over both IPv4 and IPv6.
+pub fn parse_bind(s: &str) -> Result<WebBind, String> {
+    let s = s.trim();
+    let addrs = if let Ok(port) = s.parse::<u16>() {
+        vec![SocketAddr::from(([0, 0, 0, 0], port))]
+    } else if let Ok(addr) = s.parse::<SocketAddr>() {
+        vec![addr]
+    } else {
+        if s.rsplit_once(':').is_none_or(|(_, port)| port.parse::<u16>().is_err()) {
+            return Err(format!("'{}' needs a port, as HOST:PORT", s));
+        }
+        let mut addrs: Vec<SocketAddr> = s
+            .to_socket_addrs()
+            .map_err(|e| format!("cannot resolve '{}': {}", s, e))?
+            .collect();
+        addrs.sort();
+        addrs.dedup();
+        addrs
+    };
+    if addrs.is_empty() {
+        return Err(format!("'{}' resolves to no addresses", s));
+    }
+    Ok(WebBind {
+        spec: s.to_string(),
+        addrs,
+    })
+}
+
+/// Bind every address up front, so a port in use is reported at startup
+/// rather than from inside the running server.
+pub async fn bind(binds: &[WebBind]) -> Result<Vec<TcpListener>, String> {
+    let mut listeners = Vec::new();
+    for bind in binds {
+        for addr in &bind.addrs {
+            let listener = TcpListener::bind(addr).await.map_err(|e| {
+                if bind.spec == addr.to_string() {
+                    format!("cannot listen on {}: {}", addr, e)
+                } else {
+                    format!("cannot listen on {} ({}): {}", addr, bind.spec, e)
+                }
+            })?;
+            listeners.push(listener);
+        }
+    }
+    Ok(listeners)
+}
+
+/// How the web interface is set up, beyond the shared state it shows.
+pub struct Options {
+    /// Centre of the map's range rings
+    pub receiver: Option<(f64, f64)>,
+    /// Recording directory to list and serve
+    pub recordings: Option<PathBuf>,
+    pub auth: Option<Auth>,
+    /// Serve HTTPS rather than HTTP
+    pub tls: Option<Tls>,
+    /// The broadcaster's ticks, on which the event stream is refreshed
+    pub ticks: MainFix,
+}
+
+pub async fn run(
+    listeners: Vec<TcpListener>,
+    aircraft_map: AircraftMap,
+    slots: Slots,
+    destinations: Destinations,
+    options: Options,
+) {
+    let Options {
+        receiver,
+        recordings,
+        auth,
+        tls,
+        ticks,
+    } = options;
     let state = Arc::new(AppState {
         aircraft_map,
-        tracked_callsign,
+        slots,
+        destinations,
+        receiver,
+        recordings,
+        auth,
+        csrf: auth::new_csrf_token(),
+        https: tls.is_some(),
+        snapshots: watch::Sender::new(None),
+        refresh: Notify::new(),
     });
+    tokio::spawn(events::publish(state.clone(), ticks));
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8081")
-        .await
-        .expect("Failed to bind web server on port 8081");
-    println!("Web server listening on http://0.0.0.0:8081");
+    let router = app(state);
+    if let Some(tls) = &tls {
+        tokio::spawn(tls.clone().reload_on_change());
+    }
+    let mut servers: Vec<BoxFuture<std::io::Result<()>>> = Vec::new();
+    for listener in listeners {
+        let addr = listener.local_addr().map(|a| a.to_string()).unwrap_or_default();
+        match &tls {
+            None => {
+                println!("Web server listening on http://{}", addr);
+                servers.push(axum::serve(listener, router.clone()).into_future().boxed());
+            }
+            Some(tls) => {
+                println!("Web server listening on https://{}", addr);
+                let server = match listener.into_std() {
+                    Ok(listener) => axum_server::from_tcp_rustls(listener, tls.config())
+                        .serve(router.clone().into_make_service())
+                        .boxed(),
+                    Err(e) => future::ready(Err(e)).boxed(),
+                };
+                servers.push(server);
+            }
+        }
+    }
+    if let Err(e) = future::try_join_all(servers).await {
+        eprintln!("Web server failed: {}", e);
+    }
+}
 
-    axum::serve(listener, app(state))
-        .await
-        .expect("Web server failed");
+/// Read every slot against the map. Callers hold the map lock, which is
+/// always taken before any tracker lock.
+async fn view_slots<'a>(slots: &[Slot], map: &'a HashMap<String, Aircraft>) -> Vec<SlotView<'a>> {
+    let mut views = Vec::with_capacity(slots.len());
+    for slot in slots {
+        let tracker = slot.tracker.read().await;
+        let selection = tracker.select(map);
+        let conflict = if selection.is_conflict() {
+            selection.matched.iter().map(|h| h.to_string()).collect()
+        } else {
+            Vec::new()
+        };
+        let entry = SlotEntry {
+            name: slot.name.clone(),
+            tracked: tracker.target.to_string(),
+            conflict,
+            matched: selection.matched.iter().map(|h| h.to_string()).collect(),
+            chosen: selection.chosen.map(|(hex, _)| hex.clone()),
+            rule: tracker.rule.to_string(),
+            lock: tracker.lock,
+            locked: tracker.locked.clone(),
+            status: tracker.status.to_string(),
+            lost_secs: tracker.lost_for().map(|d| d.as_secs()),
+            until: tracker.until.iter().map(|c| c.to_string()).collect(),
+            watch: tracker.watch.iter().map(|e| e.to_string()).collect(),
+        };
+        views.push(SlotView { entry, selection });
+    }
+    views
+}
+
+fn aircraft_entry(hex: &str, a: &Aircraft, views: &[SlotView]) -> AircraftEntry {
+    let slots: Vec<String> = views
+        .iter()
+        .filter(|v| v.feeds(hex))
+        .map(|v| v.entry.name.clone())
+        .collect();
+    AircraftEntry {
+        hex: hex.to_string(),
+        callsign: a.callsign.clone().unwrap_or_default(),
+        registration: a.registration.clone(),
+        type_code: a.type_code.clone(),
+        squawk: a.squawk.clone(),
+        lat: a.latitude,
+        lon: a.longitude,
+        alt_ft: a.altitude_ft,
+        gs_kt: a.ground_speed_kt,
+        track: a.track,
+        vs_fpm: a.vertical_rate_fpm,
+        on_ground: a.on_ground,
+        age: a.last_updated.elapsed().as_secs(),
+        tracking: !slots.is_empty(),
+        matched: views.iter().any(|v| v.matches(hex)),
+        slots,
+        heard: Some(a.last_updated),
+    }
 }
 
 async fn get_index(State(state): State<Arc<AppState>>) -> Html<String> {
-    Html(build_page(&state.aircraft_map, &state.tracked_callsign).await)
+    Html(
+        build_page(
+            &state.aircraft_map,
+            &state.slots,
+            &state.destinations,
+            state.recordings.is_some(),
+            &state.csrf,
+        )
+        .await,
+    )
+}
+
+async fn get_data(
+    State(state): State<Arc<AppState>>,
+    query: Result<Query<DataQuery>, QueryRejection>,
+) -> Result<Json<DataResponse>, ApiError> {
+    let Query(query) = query?;
+    let mut data = collect_data(&state).await;
+    query
+        .apply(&mut data.aircraft)
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    Ok(Json(data))
 }
 
-async fn get_data(State(state): State<Arc<AppState>>) -> Json<DataResponse> {
+/// Everything the page shows, as served by `/data`.
+async fn collect_data(state: &AppState) -> DataResponse {
     let map = state.aircraft_map.read().await;
-    let current = state.tracked_callsign.read().await.clone();
+    let views = view_slots(&state.slots, &map).await;
 
     let mut entries: Vec<AircraftEntry> = map
         .iter()
-        .map(|(hex, a)| {
-            let cs = a.callsign.as_deref().unwrap_or("");
-            let tracking = cs.eq_ignore_ascii_case(&current) && !cs.is_empty();
-            AircraftEntry {
-                hex: hex.clone(),
-                callsign: cs.to_string(),
-                lat: a.latitude,
-                lon: a.longitude,
-                alt_ft: a.altitude_ft,
-                gs_kt: a.ground_speed_kt,
-                track: a.track,
-                age: a.last_updated.elapsed().as_secs(),
-                tracking,
-            }
-        })
+        .map(|(hex, a)| aircraft_entry(hex, a, &views))
         .collect();
 
     entries.sort_by(|a, b| a.hex.cmp(&b.hex));
 
-    Json(DataResponse {
-        tracked: current,
+    let destinations = state
+        .destinations
+        .read()
+        .await
+        .iter()
+        .map(|d| DestinationEntry {
+            addr: d.addr.to_string(),
+            kind: d.kind().to_string(),
+            variant: d.variant.to_string(),
+            slot: destination_slot(&state.slots, d).to_string(),
+            sent: d.sent,
+            errors: d.errors,
+            source: destination_source(d),
+        })
+        .collect();
+
+    let slots: Vec<SlotEntry> = views.into_iter().map(|v| v.entry).collect();
+    DataResponse {
+        receiver: state.receiver,
+        main: tracker::slot_index(&state.slots, None).map(|i| slots[i].clone()),
+        slots,
         aircraft: entries,
-    })
+        destinations,
+    }
+}
+
+/// The named slot, or the primary one when no name is given.
+fn find_slot<'a>(state: &'a AppState, name: Option<&str>) -> Option<&'a Slot> {
+    tracker::slot_index(&state.slots, name).map(|i| &state.slots[i])
 }
 
 async fn post_track(
     State(state): State<Arc<AppState>>,
     Form(form): Form<TrackForm>,
 ) -> Redirect {
-    let callsign = form.callsign.trim().to_string();
-    if !callsign.is_empty() {
-        *state.tracked_callsign.write().await = callsign.clone();
-        println!("Web: now tracking callsign '{}'", callsign);
+    let Some(slot) = find_slot(&state, form.slot.as_deref()) else {
+        eprintln!("Web: ignoring track request for unknown slot '{}'", form.slot.unwrap_or_default());
+        return Redirect::to("/");
+    };
+    let parsed = form
+        .target
+        .parse::<Target>()
+        .and_then(|target| Ok((target, tracker::parse_conditions(&form.until)?)));
+    match parsed {
+        Ok((target, until)) => {
+            println!("Web: slot {} now tracking '{}'", slot.name, target);
+            let mut tracker = slot.tracker.write().await;
+            tracker.set_target(target);
+            tracker.until = until;
+        }
+        Err(e) => eprintln!("Web: ignoring track request '{}': {}", form.target, e),
+    }
+    Redirect::to("/")
+}
+
+async fn post_lock(State(state): State<Arc<AppState>>, Query(query): Query<SlotQuery>) -> Redirect {
+    let Some(slot) = find_slot(&state, query.slot.as_deref()) else {
+        return Redirect::to("/");
+    };
+    let mut tracker = slot.tracker.write().await;
+    tracker.lock();
+    match &tracker.locked {
+        Some(hex) => println!("Web: slot {} locked onto {}", slot.name, hex),
+        None => println!("Web: slot {} lock armed for '{}'", slot.name, tracker.target),
+    }
+    Redirect::to("/")
+}
+
+async fn post_release(State(state): State<Arc<AppState>>, Query(query): Query<SlotQuery>) -> Redirect {
+    let Some(slot) = find_slot(&state, query.slot.as_deref()) else {
+        return Redirect::to("/");
+    };
+    slot.tracker.write().await.release();
+    println!("Web: slot {} lock released", slot.name);
+    Redirect::to("/")
+}
+
+async fn post_watch(
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<WatchForm>,
+) -> Redirect {
+    let Some(slot) = find_slot(&state, form.slot.as_deref()) else {
+        return Redirect::to("/");
+    };
+    let parsed = form.target.parse::<Target>().and_then(|target| {
+        Ok(WatchEntry {
+            target,
+            until: tracker::parse_conditions(&form.until)?,
+        })
+    });
+    match parsed {
+        Ok(entry) => {
+            println!("Web: slot {} queued '{}'", slot.name, entry);
+            slot.tracker.write().await.watch.push_back(entry);
+        }
+        Err(e) => eprintln!("Web: ignoring watch entry '{}': {}", form.target, e),
+    }
+    Redirect::to("/")
+}
+
+async fn post_watch_remove(
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<WatchIndexForm>,
+) -> Redirect {
+    if let Some(slot) = find_slot(&state, form.slot.as_deref()) {
+        if let Some(entry) = slot.tracker.write().await.watch.remove(form.index) {
+            println!("Web: slot {} dropped '{}' from the watch list", slot.name, entry);
+        }
+    }
+    Redirect::to("/")
+}
+
+async fn post_watch_up(
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<WatchIndexForm>,
+) -> Redirect {
+    if let Some(slot) = find_slot(&state, form.slot.as_deref()) {
+        let mut tracker = slot.tracker.write().await;
+        if form.index > 0 && form.index < tracker.watch.len() {
+            tracker.watch.swap(form.index - 1, form.index);
+        }
+    }
+    Redirect::to("/")
+}
+
+async fn post_watch_next(State(state): State<Arc<AppState>>, Query(query): Query<SlotQuery>) -> Redirect {
+    if let Some(slot) = find_slot(&state, query.slot.as_deref()) {
+        if let Some(target) = slot.tracker.write().await.advance() {
+            println!("Web: slot {} skipped ahead to '{}'", slot.name, target);
+        }
     }
     Redirect::to("/")
 }
 
+fn status_html(name: &str, tracker: &Tracker, selection: &Selection, csrf: &str) -> String {
+    let mut status = format!(
+        "<div>{}: Tracking: <strong>{}</strong>",
+        escape_html(name),
+        escape_html(&tracker.target.to_string())
+    );
+    if !tracker.until.is_empty() {
+        status.push_str(&format!(
+            " until {}",
+            escape_html(&tracker::format_conditions(&tracker.until))
+        ));
+    }
+    if !tracker.watch.is_empty() {
+        status.push_str(&format!(
+            r#" <form method="POST" action="/watch/next?slot={}" style="display:inline;margin:0">{}<button type="submit">Next</button></form>"#,
+            escape_html(name),
+            auth::csrf_input(csrf)
+        ));
+    }
+    let (lock_text, lock_action, lock_label) = match (&tracker.locked, tracker.lock) {
+        (Some(hex), _) => (format!("locked to {}", escape_html(hex)), "/release", "Release"),
+        (None, true) => ("lock armed".to_string(), "/release", "Release"),
+        (None, false) => ("unlocked".to_string(), "/lock", "Lock"),
+    };
+    status.push_str(&format!(
+        r#" &mdash; {} <form method="POST" action="{}?slot={}" style="display:inline;margin:0">{}<button type="submit">{}</button></form>"#,
+        lock_text,
+        lock_action,
+        escape_html(name),
+        auth::csrf_input(csrf),
+        lock_label
+    ));
+    if tracker.status == TrackStatus::Stopped {
+        status.push_str(r#" &mdash; <span class="warn">stopped</span>"#);
+    }
+    if let Some(lost) = tracker.lost_for() {
+        let action = match tracker.status {
+            TrackStatus::Holding => "holding last fix",
+            TrackStatus::Extrapolating => "extrapolating",
+            _ => "not sending",
+        };
+        status.push_str(&format!(
+            r#" &mdash; <span class="warn">track lost {}s ago, {}</span>"#,
+            lost.as_secs(),
+            action
+        ));
+    }
+    let hexes: Vec<&str> = selection.matched.iter().map(|h| h.as_str()).collect();
+    if selection.is_conflict() {
+        status.push_str(&format!(
+            r#" &mdash; <span class="warn">conflict: {} aircraft match ({})</span>"#,
+            hexes.len(),
+            escape_html(&hexes.join(", "))
+        ));
+    } else if let Target::Nearest { .. } = tracker.target {
+        // Every positioned aircraft can match, so don't list them
+        status.push_str(&format!(
+            " &mdash; {} candidates, nearest first",
+            hexes.len()
+        ));
+    } else if !tracker.target.is_exact() {
+        status.push_str(&format!(
+            " &mdash; {} aircraft match ({}), {} first",
+            hexes.len(),
+            escape_html(&hexes.join(", ")),
+            tracker.rule
+        ));
+    }
+    status.push_str("</div>");
+    status
+}
+
+/// Rows of the watch list table, with reorder and remove buttons.
+fn watch_rows<'a>(slots: impl Iterator<Item = &'a SlotEntry>, csrf: &str) -> String {
+    let mut rows = String::new();
+    for slot in slots {
+        let name = escape_html(&slot.name);
+        for (i, entry) in slot.watch.iter().enumerate() {
+            let hidden = format!(
+                r#"<input type="hidden" name="slot" value="{}"><input type="hidden" name="index" value="{}">{}"#,
+                name,
+                i,
+                auth::csrf_input(csrf)
+            );
+            let up = if i > 0 {
+                format!(
+                    r#"<form method="POST" action="/watch/up" style="display:inline;margin:0">{}<button type="submit">Up</button></form> "#,
+                    hidden
+                )
+            } else {
+                String::new()
+            };
+            rows.push_str(&format!(
+                r#"<tr><td>{}</td><td class="r">{}</td><td>{}</td><td>{}<form method="POST" action="/watch/remove" style="display:inline;margin:0">{}<button type="submit">Remove</button></form></td></tr>
+"#,
+                name,
+                i + 1,
+                escape_html(entry),
+                up,
+                hidden
+            ));
+        }
+    }
+    rows
+}
+
+/// Name of the slot feeding a destination.
+fn destination_slot<'a>(slots: &'a [Slot], d: &'a Destination) -> &'a str {
+    d.slot.as_deref().unwrap_or(&slots[0].name)
+}
+
+fn destination_source(d: &Destination) -> String {
+    match &d.discovery {
+        Some(disc) => format!("{} (seen {}s ago)", disc.app, disc.last_seen.elapsed().as_secs()),
+        None => "configured".to_string(),
+    }
+}
+
 fn escape_html(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -113,14 +774,26 @@ fn escape_html(s: &str) -> String {
         .replace('"', "&quot;")
 }
 
-async fn build_page(aircraft_map: &AircraftMap, tracked_callsign: &TrackedCallsign) -> String {
+
+async fn build_page(
+    aircraft_map: &AircraftMap,
+    slots: &[Slot],
+    destinations: &Destinations,
+    recording: bool,
+    csrf: &str,
+) -> String {
     let map = aircraft_map.read().await;
-    let current = tracked_callsign.read().await.clone();
+    let views = view_slots(slots, &map).await;
 
     let mut rows = String::new();
     let mut sorted: Vec<_> = map.iter().collect();
     sorted.sort_by_key(|(hex, _)| hex.to_string());
 
+    let slot_options: String = slots
+        .iter()
+        .map(|s| format!(r#"<option>{}</option>"#, escape_html(&s.name)))
+        .collect();
+
     for (hex, a) in &sorted {
         let cs = a.callsign.as_deref().unwrap_or("-");
         let lat = a.latitude.map_or("-".to_string(), |v| format!("{v:.5}"));
@@ -132,36 +805,109 @@ async fn build_page(aircraft_map: &AircraftMap, tracked_callsign: &TrackedCallsi
         let trk = a.track.map_or("-".to_string(), |v| format!("{v:.0}"));
         let age = a.last_updated.elapsed().as_secs();
 
-        let is_tracked = cs.eq_ignore_ascii_case(&current) && cs != "-";
+        let reg = a.registration.as_deref().unwrap_or("-");
+        let sq = a.squawk.as_deref().unwrap_or("-");
+
+        let feeds: Vec<&str> = views
+            .iter()
+            .filter(|v| v.feeds(hex))
+            .map(|v| v.entry.name.as_str())
+            .collect();
+        let is_tracked = !feeds.is_empty();
+        let is_matched = views.iter().any(|v| v.matches(hex));
+        let is_conflict = views
+            .iter()
+            .any(|v| v.matches(hex) && v.selection.is_conflict());
         let highlight = if is_tracked {
             r#" class="tracked""#
+        } else if is_conflict {
+            r#" class="conflict""#
+        } else if is_matched {
+            r#" class="matched""#
         } else {
             ""
         };
 
-        let track_btn = if cs != "-" && !is_tracked {
-            format!(
-                r#"<form method="POST" action="/track" style="margin:0"><input type="hidden" name="callsign" value="{}"><button type="submit">Track</button></form>"#,
-                escape_html(cs)
-            )
-        } else if is_tracked {
-            "Tracking".to_string()
-        } else {
-            String::new()
-        };
+        let mut track_btn = String::new();
+        if is_tracked {
+            track_btn.push_str("Tracking");
+            if slots.len() > 1 {
+                track_btn.push_str(&format!(" ({})", escape_html(&feeds.join(", "))));
+            }
+        }
+        // With one slot there is nothing else to assign a tracked aircraft to
+        if !is_tracked || slots.len() > 1 {
+            // A callsign shared with the tracked aircraft would be ambiguous
+            let target = if cs != "-" && !is_matched {
+                cs.to_string()
+            } else {
+                format!("hex:{}", hex)
+            };
+            let slot_select = if slots.len() > 1 {
+                format!(r#"<select name="slot">{}</select>"#, slot_options)
+            } else {
+                String::new()
+            };
+            track_btn.push_str(&format!(
+                r#"<form method="POST" action="/track" style="margin:0"><input type="hidden" name="target" value="{}">{}{}<button type="submit">Track</button></form>"#,
+                escape_html(&target),
+                slot_select,
+                auth::csrf_input(csrf)
+            ));
+        }
 
         rows.push_str(&format!(
-            "<tr{}><td>{}</td><td>{}</td><td class=\"r\">{}</td><td class=\"r\">{}</td><td class=\"r\">{}</td><td class=\"r\">{}</td><td class=\"r\">{}</td><td class=\"r\">{}s</td><td>{}</td></tr>\n",
+            "<tr{}><td><a href=\"/aircraft/{hex}\">{hex}</a></td><td>{}</td><td>{}</td><td>{}</td><td class=\"r\">{}</td><td class=\"r\">{}</td><td class=\"r\">{}</td><td class=\"r\">{}</td><td class=\"r\">{}</td><td class=\"r\">{}s</td><td>{}</td></tr>\n",
             highlight,
-            escape_html(hex),
             escape_html(cs),
-            lat, lon, alt, gs, trk, age, track_btn
+            escape_html(reg),
+            escape_html(sq),
+            lat, lon, alt, gs, trk, age, track_btn,
+            hex = escape_html(hex),
         ));
     }
 
-    let count = map.len();
+    let mut status = format!("<div>{} aircraft</div>", map.len());
+    for (slot, view) in slots.iter().zip(&views) {
+        let tracker = slot.tracker.read().await;
+        status.push_str(&status_html(&slot.name, &tracker, &view.selection, csrf));
+    }
+    let watch = watch_rows(views.iter().map(|v| &v.entry), csrf);
+    let watch_slot = if slots.len() > 1 {
+        format!(r#"<select name="slot">{}</select>"#, slot_options)
+    } else {
+        String::new()
+    };
+    let map_slot = if slots.len() > 1 {
+        format!(
+            r#" &mdash; click an aircraft to track it in <select id="map-slot">{}</select>"#,
+            slot_options
+        )
+    } else {
+        " &mdash; click an aircraft to track it".to_string()
+    };
+    let recordings = if recording {
+        r#" &middot; <a href="/recordings">Recordings</a>"#
+    } else {
+        ""
+    };
+    drop(views);
     drop(map);
 
+    let mut dest_rows = String::new();
+    for d in destinations.read().await.iter() {
+        dest_rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"r\">{}</td><td class=\"r\">{}</td><td>{}</td></tr>\n",
+            d.addr,
+            d.kind(),
+            d.variant,
+            escape_html(destination_slot(slots, d)),
+            d.sent,
+            d.errors,
+            escape_html(&destination_source(d))
+        ));
+    }
+
     format!(
         r#"<!DOCTYPE html>
 <html>
@@ -170,107 +916,478 @@ async fn build_page(aircraft_map: &AircraftMap, tracked_callsign: &TrackedCallsi
 <title>adsb_xgps</title>
 <style>
 body {{ font-family: monospace; background: #1a1a2e; color: #e0e0e0; margin: 20px; }}
-h1 {{ color: #00d4ff; }}
+h1, h2 {{ color: #00d4ff; }}
 table {{ border-collapse: collapse; width: 100%; }}
 th, td {{ border: 1px solid #333; padding: 6px 10px; text-align: left; }}
 th {{ background: #16213e; color: #00d4ff; }}
 tr:nth-child(even) {{ background: #1f2b47; }}
 tr:nth-child(odd) {{ background: #1a1a2e; }}
 tr.tracked {{ background: #0a3d0a !important; }}
+tr.conflict {{ background: #4a3a0a !important; }}
+tr.matched {{ background: #0a2f4a !important; }}
+.warn {{ color: #ffb000; }}
 .r {{ text-align: right; }}
 button {{ background: #00d4ff; color: #1a1a2e; border: none; padding: 3px 10px; cursor: pointer; font-family: monospace; }}
 button:hover {{ background: #00a8cc; }}
+select, input {{ font-family: monospace; margin-right: 4px; }}
 #status {{ color: #888; margin-bottom: 10px; }}
+#map {{ width: 600px; height: 600px; max-width: 100%; background: #10101e; border: 1px solid #333; display: block; }}
+#map .ring {{ fill: none; stroke: #2a3a5a; }}
+#map .ring-label {{ fill: #556; font-size: 10px; }}
+#map .receiver {{ stroke: #ff4d4d; stroke-width: 2; }}
+#map .ac {{ cursor: pointer; }}
+#map .ac text {{ fill: #aaa; font-size: 10px; }}
+#map .other path, #map .other circle {{ fill: #00d4ff; }}
+#map .matched path, #map .matched circle {{ fill: #3fa0ff; }}
+#map .conflict path, #map .conflict circle {{ fill: #ffb000; }}
+#map .tracked path, #map .tracked circle {{ fill: #33ff66; stroke: #fff; }}
+#map .tracked text {{ fill: #33ff66; }}
+#map .trail {{ fill: none; stroke: #33ff66; stroke-opacity: 0.5; }}
+#map .trail-point {{ fill: #33ff66; }}
+#map-controls {{ color: #888; margin: 6px 0 16px; }}
+#map-controls a {{ color: #00d4ff; }}
+#map-error {{ color: #ff5555; }}
+#table-controls {{ color: #888; margin-bottom: 6px; }}
+td a {{ color: #e0e0e0; }}
+#max-age {{ width: 5em; }}
+th[data-sort] {{ cursor: pointer; }}
+th.sorted::after {{ content: ' \25B2'; }}
+th.sorted.desc::after {{ content: ' \25BC'; }}
 </style>
 </head>
 <body>
 <h1>adsb_xgps</h1>
-<div id="status">Tracking: <strong>{current}</strong> &mdash; {count} aircraft</div>
+<div id="status">{status}</div>
+<svg id="map" viewBox="-300 -300 600 600"></svg>
+<div id="map-controls"><button type="button" onclick="zoom(-1)">+</button> <button type="button" onclick="zoom(1)">&minus;</button> <span id="map-range"></span>{map_slot} &middot; <a href="/kml/link.kml">Google Earth</a> &middot; <a href="/api/aircraft.geojson?trails=true">GeoJSON</a>{recordings} <span id="map-error"></span></div>
+<div id="table-controls"><input id="filter" placeholder="hex, callsign or squawk"> <label><input type="checkbox" id="hide-unplaced"> hide without position</label> <label><input type="checkbox" id="hide-old"> hide older than</label> <input id="max-age" type="number" min="0" value="60">s</div>
 <table>
-<thead><tr><th>Hex</th><th>Callsign</th><th>Latitude</th><th>Longitude</th><th>Alt (ft)</th><th>GS (kt)</th><th>Track</th><th>Age</th><th></th></tr></thead>
+<thead><tr><th data-sort="hex">Hex</th><th data-sort="callsign">Callsign</th><th data-sort="registration">Reg</th><th data-sort="squawk">Squawk</th><th data-sort="lat">Latitude</th><th data-sort="lon">Longitude</th><th data-sort="alt_ft">Alt (ft)</th><th data-sort="gs_kt">GS (kt)</th><th data-sort="track">Track</th><th data-sort="age">Age</th><th></th></tr></thead>
 <tbody id="tbody">
 {rows}</tbody>
 </table>
+<h2>Watch list</h2>
+<table>
+<thead><tr><th>Slot</th><th>#</th><th>Then track</th><th></th></tr></thead>
+<tbody id="watch">
+{watch}</tbody>
+</table>
+<form method="POST" action="/watch" style="margin-top:6px">{csrf_input}{watch_slot}<input name="target" placeholder="DAL456"> until <input name="until" placeholder="landed,stale:10,below:1000"> <button type="submit">Queue</button></form>
+<h2>XGPS destinations</h2>
+<table>
+<thead><tr><th>Address</th><th>Kind</th><th>Variant</th><th>Slot</th><th>Sent</th><th>Errors</th><th>Source</th></tr></thead>
+<tbody id="dests">
+{dest_rows}</tbody>
+</table>
 <script>
+const csrfInput = '{csrf_input}';
+function esc(s) {{
+  return String(s).replace(/&/g, '&amp;').replace(/</g, '&lt;').replace(/>/g, '&gt;').replace(/"/g, '&quot;');
+}}
+function slotStatus(s) {{
+  let status = '<div>' + esc(s.name) + ': Tracking: <strong>' + esc(s.tracked) + '</strong>';
+  if (s.until.length) {{
+    status += ' until ' + esc(s.until.join(','));
+  }}
+  if (s.watch.length) {{
+    status += ' <form method="POST" action="/watch/next?slot=' + esc(s.name) +
+      '" style="display:inline;margin:0">' + csrfInput + '<button type="submit">Next</button></form>';
+  }}
+  const lockText = s.locked ? 'locked to ' + esc(s.locked) : s.lock ? 'lock armed' : 'unlocked';
+  const lockAction = s.lock ? '/release' : '/lock';
+  status += ' &mdash; ' + lockText + ' <form method="POST" action="' + lockAction + '?slot=' + esc(s.name) +
+    '" style="display:inline;margin:0">' + csrfInput + '<button type="submit">' +
+    (s.lock ? 'Release' : 'Lock') + '</button></form>';
+  if (s.status === 'stopped') {{
+    status += ' &mdash; <span class="warn">stopped</span>';
+  }}
+  if (s.lost_secs !== null) {{
+    const action = s.status === 'holding' ? 'holding last fix' :
+      s.status === 'extrapolating' ? 'extrapolating' : 'not sending';
+    status += ' &mdash; <span class="warn">track lost ' + s.lost_secs + 's ago, ' + action + '</span>';
+  }}
+  if (s.conflict.length > 1) {{
+    status += ' &mdash; <span class="warn">conflict: ' + s.conflict.length +
+      ' aircraft match (' + esc(s.conflict.join(', ')) + ')</span>';
+  }} else if (s.tracked.startsWith('near:')) {{
+    status += ' &mdash; ' + s.matched.length + ' candidates, nearest first';
+  }} else if (s.matched.length > 1 || s.tracked.match(/[*?\/]/)) {{
+    status += ' &mdash; ' + s.matched.length + ' aircraft match (' +
+      esc(s.matched.join(', ')) + '), ' + s.rule + ' first';
+  }}
+  return status + '</div>';
+}}
+// Ages are kept as the time each aircraft was last heard, so they keep
+// counting between pushes
+function stamp(a) {{
+  a.seen = Date.now() - a.age * 1000;
+  return a;
+}}
+function ageOf(a) {{
+  return Math.max(0, Math.floor((Date.now() - a.seen) / 1000));
+}}
+// Map: local flat projection around the receiver, or the tracked aircraft
+// without one, with four range rings out to the selected range
+const RANGES_NM = [5, 10, 25, 50, 100, 200, 400];
+let rangeIndex = 3;
+let mapData = null;
+let trails = {{}};
+let trailsFetched = 0;
+function zoom(step) {{
+  rangeIndex = Math.min(RANGES_NM.length - 1, Math.max(0, rangeIndex + step));
+  if (mapData) drawMap(mapData);
+}}
+function drawMap(d) {{
+  mapData = d;
+  const range = RANGES_NM[rangeIndex];
+  const placed = d.aircraft.filter(a => a.lat !== null && a.lon !== null);
+  let center = d.receiver;
+  if (!center) {{
+    const tracked = placed.find(a => a.tracking);
+    if (tracked) {{
+      center = [tracked.lat, tracked.lon];
+    }} else if (placed.length) {{
+      center = [placed.reduce((sum, a) => sum + a.lat, 0) / placed.length,
+        placed.reduce((sum, a) => sum + a.lon, 0) / placed.length];
+    }} else {{
+      center = [0, 0];
+    }}
+  }}
+  const scale = 280 / range;
+  const cosLat = Math.cos(center[0] * Math.PI / 180);
+  let svg = '';
+  for (let i = 1; i <= 4; i++) {{
+    svg += '<circle class="ring" r="' + 70 * i + '"/><text class="ring-label" x="3" y="' + (-70 * i - 3) + '">' +
+      range * i / 4 + ' nm</text>';
+  }}
+  if (d.receiver) {{
+    svg += '<path class="receiver" d="M-6,0 L6,0 M0,-6 L0,6"/>';
+  }}
+  const project = (lat, lon) => [((lon - center[1]) * 60 * cosLat * scale).toFixed(1),
+    (-(lat - center[0]) * 60 * scale).toFixed(1)];
+  // Every point is drawn so stray MLAT positions stand out
+  for (const hex in trails) {{
+    const points = trails[hex].map(p => project(p.lat, p.lon));
+    svg += '<polyline class="trail" points="' + points.map(p => p.join(',')).join(' ') + '"/>';
+    trails[hex].forEach((p, i) => {{
+      const alt = p.alt_ft !== null ? p.alt_ft + ' ft' : 'no altitude';
+      svg += '<circle class="trail-point" r="1.5" cx="' + points[i][0] + '" cy="' + points[i][1] + '"><title>' +
+        esc(hex) + ' ' + alt + ', ' + p.age + 's ago</title></circle>';
+    }});
+  }}
+  // Tracked aircraft last so they sit on top
+  placed.sort((x, y) => x.tracking - y.tracking);
+  for (const a of placed) {{
+    const [x, y] = project(a.lat, a.lon);
+    if (Math.abs(x) > 300 || Math.abs(y) > 300) continue;
+    const conflict = d.slots.some(s => s.conflict.includes(a.hex));
+    const cls = a.tracking ? 'tracked' : conflict ? 'conflict' : a.matched ? 'matched' : 'other';
+    const shape = a.track !== null ?
+      '<path d="M0,-9 L6,7 L0,3 L-6,7 Z" transform="rotate(' + a.track + ')"/>' : '<circle r="4"/>';
+    const alt = a.alt_ft !== null ? a.alt_ft + ' ft' : 'no altitude';
+    svg += '<g class="ac ' + cls + '" data-hex="' + esc(a.hex) + '" transform="translate(' + x + ',' + y +
+      ')"><title>' + esc(a.hex) + ' ' + esc(a.callsign) + ' ' + alt + '</title>' + shape +
+      '<text x="9" y="4">' + esc(a.callsign || a.hex) + '</text></g>';
+  }}
+  document.getElementById('map').innerHTML = svg;
+  document.getElementById('map-range').textContent = range + ' nm';
+}}
+document.getElementById('map').addEventListener('click', e => {{
+  const symbol = e.target.closest('g.ac');
+  if (!symbol) return;
+  const select = document.getElementById('map-slot');
+  const slot = select ? '?slot=' + encodeURIComponent(select.value) : '';
+  fetch('/api/track' + slot, {{
+    method: 'PUT',
+    headers: {{ 'content-type': 'application/json' }},
+    body: JSON.stringify({{ target: {{ kind: 'hex', value: symbol.dataset.hex }} }}),
+  }}).then(r => r.ok ? '' : r.text().then(text => {{
+    // API errors come as {{"error": "..."}}, a login challenge as plain text
+    try {{ text = JSON.parse(text).error || text; }} catch (_) {{}}
+    return 'Cannot track ' + symbol.dataset.hex + ': ' + r.status + ' ' + text;
+  }}), e => 'Cannot track ' + symbol.dataset.hex + ': ' + e.message).then(error => {{
+    document.getElementById('map-error').textContent = error;
+    if (!error && poller) refresh();
+  }});
+}});
+// Trails of the tracked aircraft, fetched every couple of seconds
+function fetchTrails(d) {{
+  if (Date.now() - trailsFetched < 2000) return;
+  trailsFetched = Date.now();
+  const hexes = d.aircraft.filter(a => a.tracking).map(a => a.hex);
+  Promise.all(hexes.map(hex => fetch('/api/aircraft/' + encodeURIComponent(hex) + '/trail')
+    .then(r => r.ok ? r.json() : null)
+    .catch(() => null)))
+    .then(results => {{
+      trails = {{}};
+      for (const t of results) {{
+        if (t) trails[t.hex] = t.points;
+      }}
+      if (mapData) drawMap(mapData);
+    }});
+}}
+// Table sorting and filtering, kept in localStorage. Missing values sort
+// last either way, as with /data?sort=
+const VIEW_KEY = 'adsb_xgps.table';
+const view = {{ sort: 'hex', desc: false, filter: '', hideUnplaced: false, hideOld: false, maxAge: 60 }};
+try {{
+  Object.assign(view, JSON.parse(localStorage.getItem(VIEW_KEY)) || {{}});
+}} catch (e) {{}}
+let latest = null;
+function changeView(change) {{
+  change();
+  localStorage.setItem(VIEW_KEY, JSON.stringify(view));
+  for (const th of document.querySelectorAll('th[data-sort]')) {{
+    th.className = th.dataset.sort === view.sort ? (view.desc ? 'sorted desc' : 'sorted') : '';
+  }}
+  if (latest) render(latest);
+}}
+function shown(list) {{
+  const filter = view.filter.trim().toLowerCase();
+  const rows = list.filter(a =>
+    (!filter || [a.hex, a.callsign, a.squawk].some(f => f && f.toLowerCase().includes(filter))) &&
+    (!view.hideUnplaced || (a.lat !== null && a.lon !== null)) &&
+    (!view.hideOld || ageOf(a) <= view.maxAge));
+  const value = a => {{
+    const v = view.sort === 'age' ? ageOf(a) : a[view.sort];
+    return v === '' || v === undefined ? null : v;
+  }};
+  const byHex = (x, y) => x.hex < y.hex ? -1 : x.hex > y.hex ? 1 : 0;
+  return rows.sort((x, y) => {{
+    const a = value(x), b = value(y);
+    if (a === null || b === null) return (a === null) - (b === null) || byHex(x, y);
+    return (a < b ? -1 : a > b ? 1 : 0) * (view.desc ? -1 : 1) || byHex(x, y);
+  }});
+}}
+const filterInput = document.getElementById('filter');
+const hideUnplaced = document.getElementById('hide-unplaced');
+const hideOld = document.getElementById('hide-old');
+const maxAge = document.getElementById('max-age');
+filterInput.value = view.filter;
+hideUnplaced.checked = view.hideUnplaced;
+hideOld.checked = view.hideOld;
+maxAge.value = view.maxAge;
+filterInput.addEventListener('input', () => changeView(() => view.filter = filterInput.value));
+hideUnplaced.addEventListener('change', () => changeView(() => view.hideUnplaced = hideUnplaced.checked));
+hideOld.addEventListener('change', () => changeView(() => view.hideOld = hideOld.checked));
+maxAge.addEventListener('input', () => changeView(() => view.maxAge = Math.max(0, Number(maxAge.value) || 0)));
+for (const th of document.querySelectorAll('th[data-sort]')) {{
+  th.addEventListener('click', () => changeView(() => {{
+    view.desc = view.sort === th.dataset.sort && !view.desc;
+    view.sort = th.dataset.sort;
+  }}));
+}}
+changeView(() => {{}});
+function render(d) {{
+  latest = d;
+  fetchTrails(d);
+  drawMap(d);
+  const rows = shown(d.aircraft);
+  let status = '<div>' + d.aircraft.length + ' aircraft' +
+    (rows.length < d.aircraft.length ? ', ' + rows.length + ' shown' : '') + '</div>';
+  for (const s of d.slots) {{
+    status += slotStatus(s);
+  }}
+  document.getElementById('status').innerHTML = status;
+  const multi = d.slots.length > 1;
+  const options = d.slots.map(s => '<option>' + esc(s.name) + '</option>').join('');
+  let html = '';
+  for (const a of rows) {{
+    const conflict = d.slots.some(s => s.conflict.includes(a.hex));
+    const cls = a.tracking ? ' class="tracked"' :
+      conflict ? ' class="conflict"' : a.matched ? ' class="matched"' : '';
+    const cs = a.callsign || '-';
+    const reg = a.registration || '-';
+    const sq = a.squawk || '-';
+    const lat = a.lat !== null ? a.lat.toFixed(5) : '-';
+    const lon = a.lon !== null ? a.lon.toFixed(5) : '-';
+    const alt = a.alt_ft !== null ? a.alt_ft : '-';
+    const gs = a.gs_kt !== null ? a.gs_kt : '-';
+    const trk = a.track !== null ? a.track : '-';
+    let btn = '';
+    if (a.tracking) {{
+      btn = 'Tracking' + (multi ? ' (' + esc(a.slots.join(', ')) + ')' : '');
+    }}
+    if (!a.tracking || multi) {{
+      const target = cs !== '-' && !a.matched ? cs : 'hex:' + a.hex;
+      btn += '<form method="POST" action="/track" style="margin:0">' +
+        '<input type="hidden" name="target" value="' + esc(target) + '">' +
+        (multi ? '<select name="slot">' + options + '</select>' : '') + csrfInput +
+        '<button type="submit">Track</button></form>';
+    }}
+    html += '<tr' + cls + '><td><a href="/aircraft/' + esc(a.hex) + '">' + esc(a.hex) + '</a></td><td>' + esc(cs) +
+      '</td><td>' + esc(reg) + '</td><td>' + esc(sq) +
+      '</td><td class="r">' + lat + '</td><td class="r">' + lon +
+      '</td><td class="r">' + alt + '</td><td class="r">' + gs +
+      '</td><td class="r">' + trk + '</td><td class="r">' + ageOf(a) + 's</td><td>' + btn + '</td></tr>';
+  }}
+  document.getElementById('tbody').innerHTML = html;
+  let watch = '';
+  for (const s of d.slots) {{
+    s.watch.forEach((entry, i) => {{
+      const hidden = '<input type="hidden" name="slot" value="' + esc(s.name) + '">' +
+        '<input type="hidden" name="index" value="' + i + '">' + csrfInput;
+      const up = i > 0 ? '<form method="POST" action="/watch/up" style="display:inline;margin:0">' +
+        hidden + '<button type="submit">Up</button></form> ' : '';
+      watch += '<tr><td>' + esc(s.name) + '</td><td class="r">' + (i + 1) + '</td><td>' + esc(entry) +
+        '</td><td>' + up + '<form method="POST" action="/watch/remove" style="display:inline;margin:0">' +
+        hidden + '<button type="submit">Remove</button></form></td></tr>';
+    }});
+  }}
+  document.getElementById('watch').innerHTML = watch;
+  let dests = '';
+  for (const t of d.destinations) {{
+    dests += '<tr><td>' + t.addr + '</td><td>' + t.kind + '</td><td>' + t.variant + '</td><td>' +
+      esc(t.slot) + '</td><td class="r">' + t.sent + '</td><td class="r">' + t.errors +
+      '</td><td>' + esc(t.source) + '</td></tr>';
+  }}
+  document.getElementById('dests').innerHTML = dests;
+}}
 function refresh() {{
   fetch('/data')
     .then(r => r.json())
     .then(d => {{
-      document.getElementById('status').innerHTML =
-        'Tracking: <strong>' + d.tracked + '</strong> &mdash; ' + d.aircraft.length + ' aircraft';
-      let html = '';
-      for (const a of d.aircraft) {{
-        const cls = a.tracking ? ' class="tracked"' : '';
-        const cs = a.callsign || '-';
-        const lat = a.lat !== null ? a.lat.toFixed(5) : '-';
-        const lon = a.lon !== null ? a.lon.toFixed(5) : '-';
-        const alt = a.alt_ft !== null ? a.alt_ft : '-';
-        const gs = a.gs_kt !== null ? a.gs_kt : '-';
-        const trk = a.track !== null ? a.track : '-';
-        let btn = '';
-        if (cs !== '-' && !a.tracking) {{
-          btn = '<form method="POST" action="/track" style="margin:0">' +
-            '<input type="hidden" name="callsign" value="' + cs + '">' +
-            '<button type="submit">Track</button></form>';
-        }} else if (a.tracking) {{
-          btn = 'Tracking';
-        }}
-        html += '<tr' + cls + '><td>' + a.hex + '</td><td>' + cs +
-          '</td><td class="r">' + lat + '</td><td class="r">' + lon +
-          '</td><td class="r">' + alt + '</td><td class="r">' + gs +
-          '</td><td class="r">' + trk + '</td><td class="r">' + a.age + 's</td><td>' + btn + '</td></tr>';
-      }}
-      document.getElementById('tbody').innerHTML = html;
+      d.aircraft.forEach(stamp);
+      render(d);
     }})
     .catch(() => {{}});
 }}
-setInterval(refresh, 1000);
+// Updates are pushed over /events; poll /data whenever that isn't connected
+let data = null;
+let poller = null;
+function poll() {{
+  if (!poller) {{
+    poller = setInterval(refresh, 1000);
+    refresh();
+  }}
+}}
+function stopPolling() {{
+  clearInterval(poller);
+  poller = null;
+}}
+if (window.EventSource) {{
+  const events = new EventSource('/events');
+  events.addEventListener('snapshot', e => {{
+    stopPolling();
+    data = JSON.parse(e.data);
+    data.aircraft.forEach(stamp);
+    render(data);
+  }});
+  events.addEventListener('aircraft', e => {{
+    if (!data) return;
+    const diff = JSON.parse(e.data);
+    const byHex = new Map(data.aircraft.map(a => [a.hex, a]));
+    for (const hex of diff.removed) byHex.delete(hex);
+    for (const a of diff.updated) byHex.set(a.hex, stamp(a));
+    data.aircraft = [...byHex.values()].sort((x, y) => x.hex < y.hex ? -1 : 1);
+    render(data);
+  }});
+  events.addEventListener('slots', e => {{
+    if (!data) return;
+    data.slots = JSON.parse(e.data);
+    render(data);
+  }});
+  events.addEventListener('destinations', e => {{
+    if (!data) return;
+    data.destinations = JSON.parse(e.data);
+    render(data);
+  }});
+  events.onerror = () => {{
+    data = null;
+    poll();
+  }};
+  setInterval(() => {{
+    if (data) render(data);
+  }}, 1000);
+}} else {{
+  poll();
+}}
 </script>
 </body>
 </html>"#,
-        current = escape_html(&current),
-        count = count,
+        status = status,
         rows = rows,
+        watch = watch,
+        watch_slot = watch_slot,
+        map_slot = map_slot,
+        recordings = recordings,
+        dest_rows = dest_rows,
+        csrf_input = auth::csrf_input(csrf),
     )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Aircraft;
+    use crate::tracker::Pattern;
+    use crate::xgps::{Discovery, Variant};
     use axum::body::Body;
     use http_body_util::BodyExt;
-    use std::collections::HashMap;
     use tokio::sync::RwLock;
     use tower::ServiceExt;
 
-    fn make_state(
-        callsign: &str,
+    pub(super) const TEST_CSRF: &str = "0123456789abcdef";
+
+    pub(super) fn make_state(
+        target: &str,
+        aircraft: Vec<(&str, Aircraft)>,
+    ) -> Arc<AppState> {
+        make_slots_state(&[("main", target)], aircraft)
+    }
+
+    pub(super) fn make_slots_state(
+        slots: &[(&str, &str)],
         aircraft: Vec<(&str, Aircraft)>,
     ) -> Arc<AppState> {
         let map: HashMap<String, Aircraft> = aircraft
             .into_iter()
             .map(|(hex, a)| (hex.to_string(), a))
             .collect();
+        let slots = slots
+            .iter()
+            .map(|(name, target)| Slot {
+                name: name.to_string(),
+                tracker: Arc::new(RwLock::new(Tracker::new(target.parse().unwrap()))),
+            })
+            .collect();
         Arc::new(AppState {
             aircraft_map: Arc::new(RwLock::new(map)),
-            tracked_callsign: Arc::new(RwLock::new(callsign.to_string())),
+            slots: Arc::new(slots),
+            destinations: Arc::new(RwLock::new(vec![Destination::new(
+                "255.255.255.255:49002".parse().unwrap(),
+                Variant::Standard,
+            )])),
+            receiver: None,
+            recordings: None,
+            auth: None,
+            csrf: TEST_CSRF.to_string(),
+            https: false,
+            snapshots: watch::Sender::new(None),
+            refresh: Notify::new(),
         })
     }
 
-    fn make_aircraft(callsign: Option<&str>) -> Aircraft {
+    pub(super) fn make_aircraft(callsign: Option<&str>) -> Aircraft {
         Aircraft {
             callsign: callsign.map(|s| s.to_string()),
+            registration: None,
+            type_code: None,
+            squawk: None,
             latitude: Some(40.0),
             longitude: Some(-74.0),
             altitude_ft: Some(35000.0),
             ground_speed_kt: Some(450.0),
             track: Some(270.0),
+            vertical_rate_fpm: None,
+            on_ground: None,
+            trail: crate::trail::Trail::default(),
+            updated: crate::FieldTimes::default(),
+            messages: [0; 8],
             last_updated: tokio::time::Instant::now(),
         }
     }
 
-    async fn response_body(response: axum::response::Response) -> String {
+    pub(super) async fn response_body(response: axum::response::Response) -> String {
         let bytes = response.into_body().collect().await.unwrap().to_bytes();
         String::from_utf8(bytes.to_vec()).unwrap()
     }
@@ -293,6 +1410,7 @@ mod tests {
         assert!(content_type.contains("text/html"));
         let body = response_body(response).await;
         assert!(body.contains("<title>adsb_xgps</title>"));
+        assert!(body.contains("new EventSource('/events')"));
     }
 
     #[tokio::test]
@@ -313,32 +1431,47 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn get_data_empty() {
+    async fn get_index_includes_map() {
         let state = make_state("TEST", vec![]);
         let response = app(state)
             .oneshot(
                 axum::extract::Request::builder()
-                    .uri("/data")
+                    .uri("/")
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
 
-        assert_eq!(response.status(), 200);
         let body = response_body(response).await;
-        let data: DataResponse = serde_json::from_str(&body).unwrap();
-        assert_eq!(data.tracked, "TEST");
-        assert!(data.aircraft.is_empty());
+        assert!(body.contains("<svg id=\"map\""));
+        assert!(body.contains("click an aircraft to track it"));
+        assert!(!body.contains("id=\"map-slot\""));
+        // A refused click is reported rather than looking like success
+        assert!(body.contains("<span id=\"map-error\"></span>"));
+        assert!(body.contains("r.ok ? '' : r.text()"));
     }
 
     #[tokio::test]
-    async fn get_data_with_aircraft() {
-        let state = make_state("TEST", vec![
-            ("AABB11", make_aircraft(Some("FLT1"))),
-            ("AABB22", make_aircraft(Some("FLT2"))),
-        ]);
+    async fn get_index_has_table_controls() {
+        let state = make_state("TEST", vec![]);
         let response = app(state)
+            .oneshot(axum::extract::Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        assert!(body.contains("<input id=\"filter\""));
+        assert!(body.contains("id=\"hide-unplaced\""));
+        assert!(body.contains("<th data-sort=\"alt_ft\">"));
+        assert!(body.contains("localStorage"));
+    }
+
+    #[tokio::test]
+    async fn get_data_includes_receiver() {
+        let mut state = Arc::try_unwrap(make_state("TEST", vec![])).ok().unwrap();
+        state.receiver = Some((51.5, -0.12));
+        let response = app(Arc::new(state))
             .oneshot(
                 axum::extract::Request::builder()
                     .uri("/data")
@@ -350,20 +1483,12 @@ mod tests {
 
         let body = response_body(response).await;
         let data: DataResponse = serde_json::from_str(&body).unwrap();
-        assert_eq!(data.aircraft.len(), 2);
-        assert_eq!(data.aircraft[0].hex, "AABB11");
-        assert_eq!(data.aircraft[1].hex, "AABB22");
-        assert_eq!(data.aircraft[0].callsign, "FLT1");
-        assert_eq!(data.aircraft[0].lat, Some(40.0));
-        assert_eq!(data.aircraft[0].alt_ft, Some(35000.0));
+        assert_eq!(data.receiver, Some((51.5, -0.12)));
     }
 
     #[tokio::test]
-    async fn get_data_marks_tracked_aircraft() {
-        let state = make_state("FLT1", vec![
-            ("AA1111", make_aircraft(Some("FLT1"))),
-            ("BB2222", make_aircraft(Some("FLT2"))),
-        ]);
+    async fn get_data_empty() {
+        let state = make_state("TEST", vec![]);
         let response = app(state)
             .oneshot(
                 axum::extract::Request::builder()
@@ -374,18 +1499,39 @@ mod tests {
             .await
             .unwrap();
 
+        assert_eq!(response.status(), 200);
         let body = response_body(response).await;
         let data: DataResponse = serde_json::from_str(&body).unwrap();
-        let flt1 = data.aircraft.iter().find(|a| a.callsign == "FLT1").unwrap();
-        let flt2 = data.aircraft.iter().find(|a| a.callsign == "FLT2").unwrap();
-        assert!(flt1.tracking);
-        assert!(!flt2.tracking);
+        assert_eq!(data.slots[0].tracked, "TEST");
+        assert!(data.aircraft.is_empty());
     }
 
     #[tokio::test]
-    async fn get_data_tracking_is_case_insensitive() {
-        let state = make_state("ual123", vec![
-            ("AABBCC", make_aircraft(Some("UAL123"))),
+    async fn get_data_keeps_main_slot_at_top_level() {
+        let state = make_slots_state(&[("main", "FLT1"), ("lab2", "FLT2")], vec![
+            ("AA1111", make_aircraft(Some("FLT1"))),
+            ("BB2222", make_aircraft(Some("FLT2"))),
+        ]);
+        state.slots[0].tracker.write().await.lock = true;
+        let response = app(state)
+            .oneshot(axum::extract::Request::builder().uri("/data").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(json["tracked"], "FLT1");
+        assert_eq!(json["chosen"], "AA1111");
+        assert_eq!(json["matched"], serde_json::json!(["AA1111"]));
+        assert_eq!(json["lock"], true);
+        assert_eq!(json["slots"][1]["tracked"], "FLT2");
+        assert_eq!(json["slots"][1]["chosen"], "BB2222");
+    }
+
+    #[tokio::test]
+    async fn get_data_with_aircraft() {
+        let state = make_state("TEST", vec![
+            ("AABB11", make_aircraft(Some("FLT1"))),
+            ("AABB22", make_aircraft(Some("FLT2"))),
         ]);
         let response = app(state)
             .oneshot(
@@ -399,7 +1545,676 @@ mod tests {
 
         let body = response_body(response).await;
         let data: DataResponse = serde_json::from_str(&body).unwrap();
-        assert!(data.aircraft[0].tracking);
+        assert_eq!(data.aircraft.len(), 2);
+        assert_eq!(data.aircraft[0].hex, "AABB11");
+        assert_eq!(data.aircraft[1].hex, "AABB22");
+        assert_eq!(data.aircraft[0].callsign, "FLT1");
+        assert_eq!(data.aircraft[0].lat, Some(40.0));
+        assert_eq!(data.aircraft[0].alt_ft, Some(35000.0));
+    }
+
+    async fn data_hexes(state: Arc<AppState>, uri: &str) -> Vec<String> {
+        let response = app(state)
+            .oneshot(axum::extract::Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let data: DataResponse = serde_json::from_str(&response_body(response).await).unwrap();
+        data.aircraft.into_iter().map(|a| a.hex).collect()
+    }
+
+    #[tokio::test]
+    async fn get_data_sorts_and_filters() {
+        let at = |callsign: Option<&str>, alt: Option<f64>, squawk: &str| {
+            let mut a = make_aircraft(callsign);
+            a.altitude_ft = alt;
+            a.squawk = Some(squawk.to_string());
+            a
+        };
+        let mut unplaced = at(Some("DAL9"), Some(3000.0), "7700");
+        unplaced.latitude = None;
+        let state = make_state("TEST", vec![
+            ("AA0001", at(Some("UAL1"), Some(12000.0), "1200")),
+            ("AA0002", at(None, None, "1200")),
+            ("AA0003", at(Some("UAL2"), Some(8000.0), "4521")),
+            ("AA0004", unplaced),
+        ]);
+
+        assert_eq!(data_hexes(state.clone(), "/data?sort=alt_ft").await, ["AA0004", "AA0003", "AA0001", "AA0002"]);
+        assert_eq!(data_hexes(state.clone(), "/data?sort=-alt_ft").await, ["AA0001", "AA0003", "AA0004", "AA0002"]);
+        assert_eq!(data_hexes(state.clone(), "/data?sort=callsign").await, ["AA0004", "AA0001", "AA0003", "AA0002"]);
+        assert_eq!(data_hexes(state.clone(), "/data?filter=ual").await, ["AA0001", "AA0003"]);
+        assert_eq!(data_hexes(state.clone(), "/data?filter=7700").await, ["AA0004"]);
+        assert_eq!(data_hexes(state.clone(), "/data?filter=aa000&positioned=true&sort=-hex").await, [
+            "AA0003", "AA0002", "AA0001"
+        ]);
+        assert_eq!(data_hexes(state, "/data?max_age=0").await.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn get_data_rejects_unknown_sort() {
+        let state = make_state("TEST", vec![]);
+        let response = app(state)
+            .oneshot(axum::extract::Request::builder().uri("/data?sort=speed").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        assert!(response_body(response).await.contains("unknown sort column 'speed'"));
+    }
+
+    #[tokio::test]
+    async fn get_data_marks_tracked_aircraft() {
+        let state = make_state("FLT1", vec![
+            ("AA1111", make_aircraft(Some("FLT1"))),
+            ("BB2222", make_aircraft(Some("FLT2"))),
+        ]);
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/data")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        let data: DataResponse = serde_json::from_str(&body).unwrap();
+        let flt1 = data.aircraft.iter().find(|a| a.callsign == "FLT1").unwrap();
+        let flt2 = data.aircraft.iter().find(|a| a.callsign == "FLT2").unwrap();
+        assert!(flt1.tracking);
+        assert!(!flt2.tracking);
+    }
+
+    #[tokio::test]
+    async fn get_data_tracking_is_case_insensitive() {
+        let state = make_state("ual123", vec![
+            ("AABBCC", make_aircraft(Some("UAL123"))),
+        ]);
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/data")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        let data: DataResponse = serde_json::from_str(&body).unwrap();
+        assert!(data.aircraft[0].tracking);
+    }
+
+    #[tokio::test]
+    async fn get_data_lists_destinations() {
+        let state = make_state("TEST", vec![]);
+        state.destinations.write().await[0].errors = 2;
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/data")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        let data: DataResponse = serde_json::from_str(&body).unwrap();
+        assert_eq!(data.destinations.len(), 1);
+        assert_eq!(data.destinations[0].addr, "255.255.255.255:49002");
+        assert_eq!(data.destinations[0].kind, "broadcast");
+        assert_eq!(data.destinations[0].variant, "standard");
+        assert_eq!(data.destinations[0].sent, 0);
+        assert_eq!(data.destinations[0].errors, 2);
+        assert_eq!(data.destinations[0].source, "configured");
+    }
+
+    #[tokio::test]
+    async fn index_lists_discovered_clients() {
+        let state = make_state("TEST", vec![]);
+        let mut dest = Destination::new("192.168.1.20:49002".parse().unwrap(), Variant::ForeFlight);
+        dest.discovery = Some(Discovery {
+            app: "ForeFlight".to_string(),
+            last_seen: tokio::time::Instant::now(),
+        });
+        state.destinations.write().await.push(dest);
+
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        assert!(body.contains("192.168.1.20:49002"));
+        assert!(body.contains("ForeFlight (seen 0s ago)"));
+    }
+
+    #[tokio::test]
+    async fn get_data_marks_tracked_by_hex() {
+        let state = make_state("hex:BB2222", vec![
+            ("AA1111", make_aircraft(Some("FLT1"))),
+            ("BB2222", make_aircraft(None)),
+        ]);
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/data")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        let data: DataResponse = serde_json::from_str(&body).unwrap();
+        assert_eq!(data.slots[0].tracked, "hex:BB2222");
+        assert!(!data.aircraft[0].tracking);
+        assert!(data.aircraft[1].tracking);
+    }
+
+    #[tokio::test]
+    async fn get_data_reports_conflict() {
+        let state = make_state("FLT1", vec![
+            ("AA1111", make_aircraft(Some("FLT1"))),
+            ("BB2222", make_aircraft(Some("FLT1"))),
+            ("CC3333", make_aircraft(Some("FLT2"))),
+        ]);
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/data")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        let data: DataResponse = serde_json::from_str(&body).unwrap();
+        assert_eq!(data.slots[0].conflict, vec!["AA1111", "BB2222"]);
+        let tracking: Vec<&str> = data
+            .aircraft
+            .iter()
+            .filter(|a| a.tracking)
+            .map(|a| a.hex.as_str())
+            .collect();
+        assert_eq!(tracking, vec!["AA1111"]);
+        assert!(data.aircraft[1].matched);
+        assert!(!data.aircraft[2].matched);
+    }
+
+    #[tokio::test]
+    async fn get_data_no_conflict_for_single_match() {
+        let state = make_state("FLT1", vec![
+            ("AA1111", make_aircraft(Some("FLT1"))),
+            ("BB2222", make_aircraft(Some("FLT2"))),
+        ]);
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/data")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        let data: DataResponse = serde_json::from_str(&body).unwrap();
+        assert!(data.slots[0].conflict.is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_index_shows_conflict() {
+        let state = make_state("FLT1", vec![
+            ("AA1111", make_aircraft(Some("FLT1"))),
+            ("BB2222", make_aircraft(Some("FLT1"))),
+        ]);
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        assert!(body.contains("conflict: 2 aircraft match (AA1111, BB2222)"));
+        assert!(body.contains(r#"<tr class="conflict">"#));
+        assert!(body.contains(r#"value="hex:BB2222""#));
+    }
+
+    #[tokio::test]
+    async fn get_data_reports_pattern_matches() {
+        let state = make_state("FLT*", vec![
+            ("AA1111", make_aircraft(Some("FLT1"))),
+            ("BB2222", make_aircraft(Some("FLT2"))),
+            ("CC3333", make_aircraft(Some("DAL3"))),
+        ]);
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/data")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        let data: DataResponse = serde_json::from_str(&body).unwrap();
+        assert_eq!(data.slots[0].tracked, "FLT*");
+        assert!(data.slots[0].conflict.is_empty());
+        assert_eq!(data.slots[0].matched, vec!["AA1111", "BB2222"]);
+        assert_eq!(data.slots[0].chosen.as_deref(), Some("AA1111"));
+        assert_eq!(data.slots[0].rule, "freshest");
+        assert!(data.aircraft[0].tracking);
+        assert!(data.aircraft[1].matched);
+        assert!(!data.aircraft[2].matched);
+    }
+
+    #[tokio::test]
+    async fn get_index_shows_pattern_matches() {
+        let state = make_state("FLT*", vec![
+            ("AA1111", make_aircraft(Some("FLT1"))),
+            ("BB2222", make_aircraft(Some("FLT2"))),
+        ]);
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        assert!(body.contains("2 aircraft match (AA1111, BB2222), freshest first"));
+        assert!(body.contains(r#"<tr class="matched">"#));
+        assert!(!body.contains("conflict: 2"));
+    }
+
+    #[tokio::test]
+    async fn get_index_summarises_near_target() {
+        let state = make_state("near:40,-74", vec![
+            ("AA1111", make_aircraft(Some("FLT1"))),
+            ("BB2222", make_aircraft(Some("FLT2"))),
+        ]);
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        assert!(body.contains("Tracking: <strong>near:40,-74</strong>"));
+        assert!(body.contains("2 candidates, nearest first"));
+        assert!(!body.contains("aircraft match (AA1111"));
+    }
+
+    #[tokio::test]
+    async fn post_lock_pins_followed_aircraft() {
+        let state = make_state("FLT1", vec![("AA1111", make_aircraft(Some("FLT1")))]);
+        state.slots[0].tracker.write().await.current = Some("AA1111".to_string());
+        let response = app(Arc::clone(&state))
+            .oneshot(
+                axum::extract::Request::builder()
+                    .method("POST")
+                    .uri("/lock")
+                    .body(Body::from(format!("csrf={}", TEST_CSRF)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 303);
+        let tracker = state.slots[0].tracker.read().await;
+        assert!(tracker.lock);
+        assert_eq!(tracker.locked.as_deref(), Some("AA1111"));
+    }
+
+    #[tokio::test]
+    async fn get_data_follows_lock_after_callsign_change() {
+        let state = make_state("FLT1", vec![("AA1111", make_aircraft(Some("FLT9")))]);
+        {
+            let mut tracker = state.slots[0].tracker.write().await;
+            tracker.lock = true;
+            tracker.locked = Some("AA1111".to_string());
+        }
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/data")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        let data: DataResponse = serde_json::from_str(&body).unwrap();
+        assert!(data.slots[0].lock);
+        assert_eq!(data.slots[0].locked.as_deref(), Some("AA1111"));
+        assert!(data.aircraft[0].tracking);
+        assert!(!data.aircraft[0].matched);
+    }
+
+    #[tokio::test]
+    async fn post_release_clears_lock() {
+        let state = make_state("FLT1", vec![]);
+        {
+            let mut tracker = state.slots[0].tracker.write().await;
+            tracker.lock = true;
+            tracker.locked = Some("AA1111".to_string());
+        }
+        let _ = app(Arc::clone(&state))
+            .oneshot(
+                axum::extract::Request::builder()
+                    .method("POST")
+                    .uri("/release")
+                    .body(Body::from(format!("csrf={}", TEST_CSRF)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let tracker = state.slots[0].tracker.read().await;
+        assert!(!tracker.lock);
+        assert!(tracker.locked.is_none());
+    }
+
+    #[tokio::test]
+    async fn get_index_shows_lock_state() {
+        let state = make_state("FLT1", vec![]);
+        state.slots[0].tracker.write().await.locked = Some("AA1111".to_string());
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        assert!(body.contains("locked to AA1111"));
+        assert!(body.contains(r#"action="/release?slot=main""#));
+    }
+
+    #[tokio::test]
+    async fn get_data_reports_lost_track() {
+        let state = make_state("FLT1", vec![("AA1111", make_aircraft(Some("FLT1")))]);
+        {
+            let map = state.aircraft_map.read().await;
+            let mut tracker = state.slots[0].tracker.write().await;
+            tracker.on_loss = tracker::LossMode::Hold;
+            assert!(tracker.update(&map).is_some());
+        }
+        state.aircraft_map.write().await.clear();
+        {
+            let map = state.aircraft_map.read().await;
+            assert!(state.slots[0].tracker.write().await.update(&map).is_some());
+        }
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/data")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        let data: DataResponse = serde_json::from_str(&body).unwrap();
+        assert_eq!(data.slots[0].status, "holding");
+        assert_eq!(data.slots[0].lost_secs, Some(0));
+    }
+
+    #[tokio::test]
+    async fn get_index_shows_lost_track() {
+        let state = make_state("FLT1", vec![("AA1111", make_aircraft(Some("FLT1")))]);
+        {
+            let map = state.aircraft_map.read().await;
+            state.slots[0].tracker.write().await.update(&map);
+        }
+        state.aircraft_map.write().await.clear();
+        {
+            let map = state.aircraft_map.read().await;
+            assert!(state.slots[0].tracker.write().await.update(&map).is_none());
+        }
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        assert!(body.contains("track lost 0s ago, not sending"));
+    }
+
+    #[tokio::test]
+    async fn get_data_reports_slot_per_aircraft() {
+        let state = make_slots_state(&[("main", "FLT1"), ("lab2", "FLT2")], vec![
+            ("AA1111", make_aircraft(Some("FLT1"))),
+            ("BB2222", make_aircraft(Some("FLT2"))),
+            ("CC3333", make_aircraft(Some("FLT3"))),
+        ]);
+        let mut dest = Destination::new("10.0.0.2:49002".parse().unwrap(), Variant::Standard);
+        dest.slot = Some("lab2".to_string());
+        state.destinations.write().await.push(dest);
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/data")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        let data: DataResponse = serde_json::from_str(&body).unwrap();
+        assert_eq!(data.slots.len(), 2);
+        assert_eq!(data.slots[1].name, "lab2");
+        assert_eq!(data.slots[1].chosen.as_deref(), Some("BB2222"));
+        assert_eq!(data.aircraft[0].slots, vec!["main"]);
+        assert_eq!(data.aircraft[1].slots, vec!["lab2"]);
+        assert!(data.aircraft[2].slots.is_empty());
+        assert!(!data.aircraft[2].tracking);
+        assert_eq!(data.destinations[0].slot, "main");
+        assert_eq!(data.destinations[1].slot, "lab2");
+    }
+
+    #[tokio::test]
+    async fn get_index_offers_slot_choice() {
+        let state = make_slots_state(&[("main", "FLT1"), ("lab2", "FLT2")], vec![
+            ("AA1111", make_aircraft(Some("FLT1"))),
+        ]);
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        assert!(body.contains("lab2: Tracking: <strong>FLT2</strong>"));
+        assert!(body.contains("Tracking (main)"));
+        assert!(body.contains(r#"<select name="slot"><option>main</option><option>lab2</option></select>"#));
+        assert!(body.contains(r#"action="/lock?slot=lab2""#));
+    }
+
+    #[tokio::test]
+    async fn post_track_assigns_slot() {
+        let state = make_slots_state(&[("main", "FLT1"), ("lab2", "FLT2")], vec![]);
+        let _ = app(Arc::clone(&state))
+            .oneshot(
+                axum::extract::Request::builder()
+                    .method("POST")
+                    .uri("/track")
+                    .header("content-type", "application/x-www-form-urlencoded")
+                    .body(Body::from(format!("target=hex%3AAA1111&slot=lab2&csrf={}", TEST_CSRF)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(state.slots[1].tracker.read().await.target, Target::Hex("AA1111".to_string()));
+        assert_eq!(
+            state.slots[0].tracker.read().await.target,
+            Target::Callsign(Pattern::Exact("FLT1".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn post_track_unknown_slot_ignored() {
+        let state = make_state("KEEP", vec![]);
+        let _ = app(Arc::clone(&state))
+            .oneshot(
+                axum::extract::Request::builder()
+                    .method("POST")
+                    .uri("/track")
+                    .header("content-type", "application/x-www-form-urlencoded")
+                    .body(Body::from(format!("target=NEW&slot=nope&csrf={}", TEST_CSRF)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let current = state.slots[0].tracker.read().await.target.clone();
+        assert_eq!(current, Target::Callsign(Pattern::Exact("KEEP".to_string())));
+    }
+
+    #[tokio::test]
+    async fn post_lock_targets_named_slot() {
+        let state = make_slots_state(&[("main", "FLT1"), ("lab2", "FLT2")], vec![]);
+        let _ = app(Arc::clone(&state))
+            .oneshot(
+                axum::extract::Request::builder()
+                    .method("POST")
+                    .uri("/lock?slot=lab2")
+                    .body(Body::from(format!("csrf={}", TEST_CSRF)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert!(!state.slots[0].tracker.read().await.lock);
+        assert!(state.slots[1].tracker.read().await.lock);
+    }
+
+    async fn post_form(state: &Arc<AppState>, uri: &str, body: &str) {
+        let _ = app(Arc::clone(state))
+            .oneshot(
+                axum::extract::Request::builder()
+                    .method("POST")
+                    .uri(uri)
+                    .header("content-type", "application/x-www-form-urlencoded")
+                    .body(Body::from(format!("{}&csrf={}", body, TEST_CSRF)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn post_watch_edits_queue() {
+        let state = make_state("FLT1", vec![]);
+        post_form(&state, "/watch", "target=DAL456&until=landed").await;
+        post_form(&state, "/watch", "target=hex%3AAA1111&until=").await;
+        post_form(&state, "/watch", "target=DAL789&until=sunset").await;
+        {
+            let tracker = state.slots[0].tracker.read().await;
+            let watch: Vec<String> = tracker.watch.iter().map(|e| e.to_string()).collect();
+            assert_eq!(watch, vec!["DAL456 until landed", "hex:AA1111"]);
+        }
+
+        post_form(&state, "/watch/up", "index=1").await;
+        post_form(&state, "/watch/remove", "index=1").await;
+        let tracker = state.slots[0].tracker.read().await;
+        let watch: Vec<String> = tracker.watch.iter().map(|e| e.to_string()).collect();
+        assert_eq!(watch, vec!["hex:AA1111"]);
+    }
+
+    #[tokio::test]
+    async fn post_watch_next_advances() {
+        let state = make_state("FLT1", vec![]);
+        post_form(&state, "/watch", "target=DAL456&until=below%3A1000").await;
+        post_form(&state, "/watch/next", "").await;
+
+        let tracker = state.slots[0].tracker.read().await;
+        assert_eq!(tracker.target.to_string(), "DAL456");
+        assert_eq!(tracker.until, vec![tracker::SwitchCondition::Below(1000.0)]);
+        assert!(tracker.watch.is_empty());
+    }
+
+    #[tokio::test]
+    async fn post_track_sets_conditions() {
+        let state = make_state("FLT1", vec![]);
+        post_form(&state, "/track", "target=DAL123&until=landed%2Cstale%3A10").await;
+        let tracker = state.slots[0].tracker.read().await;
+        assert_eq!(tracker.target.to_string(), "DAL123");
+        assert_eq!(tracker.until.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn index_shows_watch_list() {
+        let state = make_state("FLT1", vec![]);
+        {
+            let mut tracker = state.slots[0].tracker.write().await;
+            tracker.until = vec![tracker::SwitchCondition::Landed];
+            tracker.watch.push_back("DAL456 until stale:5".parse().unwrap());
+        }
+        let response = app(Arc::clone(&state))
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        assert!(body.contains("<strong>FLT1</strong> until landed"));
+        assert!(body.contains("<td>DAL456 until stale:5</td>"));
+        assert!(body.contains(r#"action="/watch/next?slot=main""#));
+
+        let response = app(state)
+            .oneshot(
+                axum::extract::Request::builder()
+                    .uri("/data")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let data: DataResponse = serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(data.slots[0].until, vec!["landed"]);
+        assert_eq!(data.slots[0].watch, vec!["DAL456 until stale:5"]);
     }
 
     #[tokio::test]
@@ -411,7 +2226,7 @@ mod tests {
                     .method("POST")
                     .uri("/track")
                     .header("content-type", "application/x-www-form-urlencoded")
-                    .body(Body::from("callsign=NEW123"))
+                    .body(Body::from(format!("callsign=NEW123&csrf={}", TEST_CSRF)))
                     .unwrap(),
             )
             .await
@@ -419,8 +2234,46 @@ mod tests {
 
         assert_eq!(response.status(), 303);
         assert_eq!(response.headers().get("location").unwrap(), "/");
-        let current = state.tracked_callsign.read().await.clone();
-        assert_eq!(current, "NEW123");
+        let current = state.slots[0].tracker.read().await.target.clone();
+        assert_eq!(current, Target::Callsign(Pattern::Exact("NEW123".to_string())));
+    }
+
+    #[tokio::test]
+    async fn post_track_accepts_target_syntax() {
+        let state = make_state("OLD", vec![]);
+        let _ = app(Arc::clone(&state))
+            .oneshot(
+                axum::extract::Request::builder()
+                    .method("POST")
+                    .uri("/track")
+                    .header("content-type", "application/x-www-form-urlencoded")
+                    .body(Body::from(format!("target=squawk%3A7700&csrf={}", TEST_CSRF)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let current = state.slots[0].tracker.read().await.target.clone();
+        assert_eq!(current, Target::Squawk("7700".to_string()));
+    }
+
+    #[tokio::test]
+    async fn post_track_invalid_target_ignored() {
+        let state = make_state("KEEP", vec![]);
+        let _ = app(Arc::clone(&state))
+            .oneshot(
+                axum::extract::Request::builder()
+                    .method("POST")
+                    .uri("/track")
+                    .header("content-type", "application/x-www-form-urlencoded")
+                    .body(Body::from(format!("target=hex%3AZZZ&csrf={}", TEST_CSRF)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let current = state.slots[0].tracker.read().await.target.clone();
+        assert_eq!(current, Target::Callsign(Pattern::Exact("KEEP".to_string())));
     }
 
     #[tokio::test]
@@ -432,14 +2285,14 @@ mod tests {
                     .method("POST")
                     .uri("/track")
                     .header("content-type", "application/x-www-form-urlencoded")
-                    .body(Body::from("callsign="))
+                    .body(Body::from(format!("callsign=&csrf={}", TEST_CSRF)))
                     .unwrap(),
             )
             .await
             .unwrap();
 
-        let current = state.tracked_callsign.read().await.clone();
-        assert_eq!(current, "KEEP");
+        let current = state.slots[0].tracker.read().await.target.clone();
+        assert_eq!(current, Target::Callsign(Pattern::Exact("KEEP".to_string())));
     }
 
     #[tokio::test]
@@ -458,6 +2311,30 @@ mod tests {
         assert_eq!(response.status(), 404);
     }
 
+    #[test]
+    fn parse_bind_forms() {
+        assert_eq!(parse_bind("8090").unwrap().addrs, vec!["0.0.0.0:8090".parse().unwrap()]);
+        assert_eq!(parse_bind("127.0.0.1:8090").unwrap().addrs, vec!["127.0.0.1:8090".parse().unwrap()]);
+        assert_eq!(parse_bind("[::]:8090").unwrap().addrs, vec!["[::]:8090".parse().unwrap()]);
+        let local = parse_bind("localhost:8090").unwrap();
+        assert_eq!(local.spec, "localhost:8090");
+        assert!(local.addrs.iter().all(|a| a.ip().is_loopback() && a.port() == 8090));
+        assert!(parse_bind("localhost").is_err());
+        assert!(parse_bind("0.0.0.0:http").is_err());
+    }
+
+    #[tokio::test]
+    async fn bind_reports_port_in_use() {
+        let taken = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = taken.local_addr().unwrap();
+        let spec = parse_bind(&addr.to_string()).unwrap();
+
+        let err = bind(&[spec]).await.unwrap_err();
+        assert!(err.starts_with(&format!("cannot listen on {}: ", addr)), "{}", err);
+        let free = parse_bind("127.0.0.1:0").unwrap();
+        assert_eq!(bind(&[free.clone(), free]).await.unwrap().len(), 2);
+    }
+
     #[test]
     fn escape_html_special_chars() {
         assert_eq!(escape_html("<script>"), "&lt;script&gt;");
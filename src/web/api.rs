@@ -0,0 +1,590 @@
+use super::{aircraft_entry, find_slot, view_slots, AppState, AircraftEntry, SlotEntry, SlotQuery};
+use crate::tracker::{self, Pattern, SwitchCondition, Target};
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, put};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// JSON control for scripts and button panels. `/api/track` replaces a
+/// slot's target; `/api/track/lock` only arms or releases its lock, so a
+/// "release" button doesn't lose the aircraft being followed.
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/api/track", get(get_track).put(put_track).delete(delete_track))
+        .route("/api/track/lock", put(put_lock).delete(delete_lock))
+        .route("/api/aircraft/{hex}", get(get_aircraft))
+        .route("/api/aircraft/{hex}/trail", get(get_trail))
+}
+
+/// Error reply, sent as `{"error": "..."}` with a matching status code.
+pub(super) struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    pub(super) fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn unknown_slot(name: Option<&str>) -> Self {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("no slot named '{}'", name.unwrap_or_default()),
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(ErrorBody { error: self.message })).into_response()
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::new(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::new(rejection.status(), rejection.body_text())
+    }
+}
+
+/// A target as JSON, e.g. `{"kind": "hex", "value": "A1B2C3"}` or
+/// `{"kind": "near", "lat": 40.6, "lon": -73.8}`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum TargetSpec {
+    Callsign {
+        value: String,
+    },
+    Hex {
+        value: String,
+    },
+    Reg {
+        value: String,
+    },
+    Squawk {
+        value: String,
+    },
+    Near {
+        lat: f64,
+        lon: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min_alt_ft: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_alt_ft: Option<f64>,
+    },
+}
+
+impl TargetSpec {
+    /// Goes through the CLI syntax so both share the same validation.
+    fn to_target(&self) -> Result<Target, String> {
+        let text = match self {
+            TargetSpec::Callsign { value } => format!("callsign:{}", value),
+            TargetSpec::Hex { value } => format!("hex:{}", value),
+            TargetSpec::Reg { value } => format!("reg:{}", value),
+            TargetSpec::Squawk { value } => format!("squawk:{}", value),
+            TargetSpec::Near {
+                lat,
+                lon,
+                min_alt_ft,
+                max_alt_ft,
+            } => match (min_alt_ft, max_alt_ft) {
+                (None, None) => format!("near:{},{}", lat, lon),
+                (Some(min), Some(max)) => format!("near:{},{},{}-{}", lat, lon, min, max),
+                _ => return Err("min_alt_ft and max_alt_ft must be given together".to_string()),
+            },
+        };
+        text.parse()
+    }
+}
+
+impl From<&Target> for TargetSpec {
+    fn from(target: &Target) -> Self {
+        let pattern = |p: &Pattern| p.to_string();
+        match target {
+            Target::Callsign(p) => TargetSpec::Callsign { value: pattern(p) },
+            Target::Hex(hex) => TargetSpec::Hex { value: hex.clone() },
+            Target::Registration(p) => TargetSpec::Reg { value: pattern(p) },
+            Target::Squawk(code) => TargetSpec::Squawk { value: code.clone() },
+            Target::Nearest { lat, lon, band } => TargetSpec::Near {
+                lat: *lat,
+                lon: *lon,
+                min_alt_ft: band.map(|(min, _)| min),
+                max_alt_ft: band.map(|(_, max)| max),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TrackResponse {
+    target: TargetSpec,
+    #[serde(flatten)]
+    slot: SlotEntry,
+}
+
+#[derive(Deserialize)]
+struct TrackRequest {
+    target: TargetSpec,
+    /// Switch conditions ending the target, as in `--until`
+    #[serde(default)]
+    until: Vec<String>,
+    /// Lock onto the aircraft the target acquires. This comes with a new
+    /// target; PUT or DELETE `/api/track/lock` changes the lock alone.
+    #[serde(default)]
+    lock: Option<bool>,
+}
+
+async fn track_response(state: &AppState, slot: Option<&str>) -> Result<TrackResponse, ApiError> {
+    let index = tracker::slot_index(&state.slots, slot).ok_or_else(|| ApiError::unknown_slot(slot))?;
+    let map = state.aircraft_map.read().await;
+    let views = view_slots(&state.slots[index..=index], &map).await;
+    let target = TargetSpec::from(&state.slots[index].tracker.read().await.target);
+    let slot = views.into_iter().next().expect("one slot viewed").entry;
+    Ok(TrackResponse { target, slot })
+}
+
+async fn get_track(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SlotQuery>,
+) -> Result<Json<TrackResponse>, ApiError> {
+    Ok(Json(track_response(&state, query.slot.as_deref()).await?))
+}
+
+async fn put_track(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SlotQuery>,
+    body: Result<Json<TrackRequest>, JsonRejection>,
+) -> Result<Json<TrackResponse>, ApiError> {
+    let Json(request) = body?;
+    let slot = find_slot(&state, query.slot.as_deref())
+        .ok_or_else(|| ApiError::unknown_slot(query.slot.as_deref()))?;
+    let unprocessable = |e: String| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e);
+    let target = request.target.to_target().map_err(unprocessable)?;
+    let until = request
+        .until
+        .iter()
+        .map(|c| c.parse::<SwitchCondition>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(unprocessable)?;
+
+    {
+        let mut tracker = slot.tracker.write().await;
+        println!("API: slot {} now tracking '{}'", slot.name, target);
+        tracker.set_target(target);
+        tracker.until = until;
+        match request.lock {
+            Some(true) => tracker.lock(),
+            Some(false) => tracker.release(),
+            None => {}
+        }
+    }
+    Ok(Json(track_response(&state, Some(&slot.name)).await?))
+}
+
+async fn delete_track(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SlotQuery>,
+) -> Result<StatusCode, ApiError> {
+    let slot = find_slot(&state, query.slot.as_deref())
+        .ok_or_else(|| ApiError::unknown_slot(query.slot.as_deref()))?;
+    slot.tracker.write().await.stop();
+    println!("API: slot {} stopped", slot.name);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Arm the lock, pinning the aircraft followed now, without touching the
+/// target or the aircraft chosen for it.
+async fn put_lock(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SlotQuery>,
+) -> Result<Json<TrackResponse>, ApiError> {
+    let slot = find_slot(&state, query.slot.as_deref())
+        .ok_or_else(|| ApiError::unknown_slot(query.slot.as_deref()))?;
+    {
+        let mut tracker = slot.tracker.write().await;
+        tracker.lock();
+        match &tracker.locked {
+            Some(hex) => println!("API: slot {} locked onto {}", slot.name, hex),
+            None => println!("API: slot {} lock armed for '{}'", slot.name, tracker.target),
+        }
+    }
+    Ok(Json(track_response(&state, Some(&slot.name)).await?))
+}
+
+/// Release the lock; the target goes back to matching as usual.
+async fn delete_lock(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SlotQuery>,
+) -> Result<Json<TrackResponse>, ApiError> {
+    let slot = find_slot(&state, query.slot.as_deref())
+        .ok_or_else(|| ApiError::unknown_slot(query.slot.as_deref()))?;
+    slot.tracker.write().await.release();
+    println!("API: slot {} lock released", slot.name);
+    Ok(Json(track_response(&state, Some(&slot.name)).await?))
+}
+
+async fn get_aircraft(
+    State(state): State<Arc<AppState>>,
+    Path(hex): Path<String>,
+) -> Result<Json<AircraftEntry>, ApiError> {
+    let hex = hex.to_ascii_uppercase();
+    let map = state.aircraft_map.read().await;
+    let aircraft = map
+        .get(&hex)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("no aircraft {}", hex)))?;
+    let views = view_slots(&state.slots, &map).await;
+    Ok(Json(aircraft_entry(&hex, aircraft, &views)))
+}
+
+/// One position of a trail; `age` is seconds since it was reported.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct TrailPointEntry {
+    lat: f64,
+    lon: f64,
+    alt_ft: Option<f64>,
+    gs_kt: Option<f64>,
+    age: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct TrailResponse {
+    hex: String,
+    /// Oldest first
+    points: Vec<TrailPointEntry>,
+}
+
+async fn get_trail(
+    State(state): State<Arc<AppState>>,
+    Path(hex): Path<String>,
+) -> Result<Json<TrailResponse>, ApiError> {
+    let hex = hex.to_ascii_uppercase();
+    let map = state.aircraft_map.read().await;
+    let aircraft = map
+        .get(&hex)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("no aircraft {}", hex)))?;
+    let points = aircraft
+        .trail
+        .iter()
+        .map(|p| TrailPointEntry {
+            lat: p.latitude,
+            lon: p.longitude,
+            alt_ft: p.altitude_ft,
+            gs_kt: p.ground_speed_kt,
+            age: p.at.elapsed().as_secs(),
+        })
+        .collect();
+    Ok(Json(TrailResponse { hex, points }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{make_aircraft, make_slots_state, make_state, response_body};
+    use super::super::{app, DataResponse};
+    use super::*;
+    use crate::trail::TrailPoint;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use tokio::time::Instant;
+    use tower::ServiceExt;
+
+    fn json_request(method: &str, uri: &str, body: &str) -> Request {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    fn get(uri: &str) -> Request {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn target_spec_round_trips() {
+        for text in ["UAL*", "hex:A1B2C3", "reg:N12345", "squawk:7700", "near:40.5,-74,0-5000"] {
+            let target: Target = text.parse().unwrap();
+            assert_eq!(TargetSpec::from(&target).to_target(), Ok(target));
+        }
+    }
+
+    #[test]
+    fn near_spec_needs_both_altitudes() {
+        let spec = TargetSpec::Near {
+            lat: 40.0,
+            lon: -74.0,
+            min_alt_ft: Some(1000.0),
+            max_alt_ft: None,
+        };
+        assert!(spec.to_target().is_err());
+    }
+
+    #[tokio::test]
+    async fn get_track_reports_target() {
+        let state = make_state("hex:AA1111", vec![("AA1111", make_aircraft(Some("FLT1")))]);
+        let response = app(state).oneshot(get("/api/track")).await.unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body: TrackResponse = serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(
+            body.target,
+            TargetSpec::Hex {
+                value: "AA1111".to_string()
+            }
+        );
+        assert_eq!(body.slot.name, "main");
+        assert_eq!(body.slot.chosen.as_deref(), Some("AA1111"));
+    }
+
+    #[tokio::test]
+    async fn put_track_sets_target() {
+        let state = make_slots_state(&[("main", "FLT1"), ("lab2", "FLT2")], vec![]);
+        let response = app(Arc::clone(&state))
+            .oneshot(json_request(
+                "PUT",
+                "/api/track?slot=lab2",
+                r#"{"target": {"kind": "squawk", "value": "7700"}, "until": ["landed"], "lock": true}"#,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body: TrackResponse = serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(body.slot.tracked, "squawk:7700");
+        assert_eq!(body.slot.until, vec!["landed"]);
+        assert!(body.slot.lock);
+        let tracker = state.slots[1].tracker.read().await;
+        assert_eq!(tracker.target, Target::Squawk("7700".to_string()));
+        assert_eq!(state.slots[0].tracker.read().await.target.to_string(), "FLT1");
+    }
+
+    #[tokio::test]
+    async fn put_track_rejects_invalid_target() {
+        let state = make_state("KEEP", vec![]);
+        let response = app(Arc::clone(&state))
+            .oneshot(json_request(
+                "PUT",
+                "/api/track",
+                r#"{"target": {"kind": "hex", "value": "ZZZ"}}"#,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 422);
+        let body: ErrorBody = serde_json::from_str(&response_body(response).await).unwrap();
+        assert!(body.error.contains("ZZZ"));
+        assert_eq!(state.slots[0].tracker.read().await.target.to_string(), "KEEP");
+    }
+
+    #[tokio::test]
+    async fn put_track_rejects_bad_condition() {
+        let state = make_state("KEEP", vec![]);
+        let response = app(state)
+            .oneshot(json_request(
+                "PUT",
+                "/api/track",
+                r#"{"target": {"kind": "callsign", "value": "DAL1"}, "until": ["sunset"]}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 422);
+    }
+
+    #[tokio::test]
+    async fn put_track_rejects_malformed_json() {
+        let state = make_state("KEEP", vec![]);
+        let response = app(Arc::clone(&state))
+            .oneshot(json_request("PUT", "/api/track", "{not json"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        let body: ErrorBody = serde_json::from_str(&response_body(response).await).unwrap();
+        assert!(!body.error.is_empty());
+
+        let response = app(state)
+            .oneshot(json_request("PUT", "/api/track", r#"{"target": {"kind": "tail"}}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 422);
+    }
+
+    #[tokio::test]
+    async fn put_track_requires_json_content_type() {
+        let state = make_state("KEEP", vec![]);
+        let request = Request::builder()
+            .method("PUT")
+            .uri("/api/track")
+            .body(Body::from(r#"{"target": {"kind": "hex", "value": "AA1111"}}"#))
+            .unwrap();
+        let response = app(state).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), 415);
+    }
+
+    #[tokio::test]
+    async fn unknown_slot_is_not_found() {
+        let state = make_state("KEEP", vec![]);
+        let response = app(Arc::clone(&state)).oneshot(get("/api/track?slot=nope")).await.unwrap();
+        assert_eq!(response.status(), 404);
+        let body: ErrorBody = serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(body.error, "no slot named 'nope'");
+
+        let request = Request::builder()
+            .method("DELETE")
+            .uri("/api/track?slot=nope")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(app(state).oneshot(request).await.unwrap().status(), 404);
+    }
+
+    #[tokio::test]
+    async fn delete_track_stops_slot() {
+        let state = make_state("FLT1", vec![("AA1111", make_aircraft(Some("FLT1")))]);
+        let request = Request::builder()
+            .method("DELETE")
+            .uri("/api/track")
+            .body(Body::empty())
+            .unwrap();
+        let response = app(Arc::clone(&state)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), 204);
+
+        let response = app(state).oneshot(get("/data")).await.unwrap();
+        let data: DataResponse = serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(data.slots[0].status, "stopped");
+        assert!(!data.aircraft[0].tracking);
+    }
+
+    #[tokio::test]
+    async fn lock_endpoint_keeps_target() {
+        let state = make_state("FLT1", vec![("AA1111", make_aircraft(Some("FLT1")))]);
+        state.slots[0].tracker.write().await.current = Some("AA1111".to_string());
+
+        let response = app(Arc::clone(&state))
+            .oneshot(json_request("PUT", "/api/track/lock", ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body: TrackResponse = serde_json::from_str(&response_body(response).await).unwrap();
+        assert!(body.slot.lock);
+        assert_eq!(body.slot.locked.as_deref(), Some("AA1111"));
+        {
+            let tracker = state.slots[0].tracker.read().await;
+            assert_eq!(tracker.target.to_string(), "FLT1");
+            assert_eq!(tracker.current.as_deref(), Some("AA1111"));
+        }
+
+        let response = app(Arc::clone(&state))
+            .oneshot(json_request("DELETE", "/api/track/lock", ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body: TrackResponse = serde_json::from_str(&response_body(response).await).unwrap();
+        assert!(!body.slot.lock);
+        assert!(body.slot.locked.is_none());
+        let tracker = state.slots[0].tracker.read().await;
+        assert_eq!(tracker.target.to_string(), "FLT1");
+        assert_eq!(tracker.current.as_deref(), Some("AA1111"));
+    }
+
+    #[tokio::test]
+    async fn release_button_targets_named_slot() {
+        let state = make_slots_state(&[("main", "FLT1"), ("lab2", "FLT2")], vec![]);
+        for slot in state.slots.iter() {
+            let mut tracker = slot.tracker.write().await;
+            tracker.lock = true;
+            tracker.current = Some("BB2222".to_string());
+            tracker.locked = Some("BB2222".to_string());
+        }
+        let response = app(Arc::clone(&state))
+            .oneshot(json_request("DELETE", "/api/track/lock?slot=lab2", ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(state.slots[0].tracker.read().await.lock);
+        let tracker = state.slots[1].tracker.read().await;
+        assert!(!tracker.lock);
+        assert_eq!(tracker.target.to_string(), "FLT2");
+        assert_eq!(tracker.current.as_deref(), Some("BB2222"));
+        drop(tracker);
+
+        let response = app(state)
+            .oneshot(json_request("DELETE", "/api/track/lock?slot=nope", ""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+        let body: ErrorBody = serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(body.error, "no slot named 'nope'");
+    }
+
+    #[tokio::test]
+    async fn get_aircraft_by_hex() {
+        let state = make_state("FLT1", vec![("AA1111", make_aircraft(Some("FLT1")))]);
+        let response = app(state).oneshot(get("/api/aircraft/aa1111")).await.unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body: AircraftEntry = serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(body.hex, "AA1111");
+        assert_eq!(body.callsign, "FLT1");
+        assert_eq!(body.slots, vec!["main"]);
+    }
+
+    #[tokio::test]
+    async fn get_unknown_aircraft_is_not_found() {
+        let state = make_state("FLT1", vec![]);
+        let response = app(state).oneshot(get("/api/aircraft/BB2222")).await.unwrap();
+        assert_eq!(response.status(), 404);
+        let body: ErrorBody = serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(body.error, "no aircraft BB2222");
+    }
+
+    #[tokio::test]
+    async fn get_trail_lists_positions_oldest_first() {
+        let mut aircraft = make_aircraft(Some("FLT1"));
+        for (lat, alt) in [(40.0, 5000.0), (40.01, 5200.0)] {
+            aircraft.trail.push(TrailPoint {
+                latitude: lat,
+                longitude: -74.0,
+                altitude_ft: Some(alt),
+                ground_speed_kt: Some(250.0),
+                at: Instant::now(),
+            });
+        }
+        let state = make_state("FLT1", vec![("AA1111", aircraft)]);
+        let response = app(state).oneshot(get("/api/aircraft/aa1111/trail")).await.unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body: TrailResponse = serde_json::from_str(&response_body(response).await).unwrap();
+        assert_eq!(body.hex, "AA1111");
+        assert_eq!(
+            body.points,
+            vec![
+                TrailPointEntry { lat: 40.0, lon: -74.0, alt_ft: Some(5000.0), gs_kt: Some(250.0), age: 0 },
+                TrailPointEntry { lat: 40.01, lon: -74.0, alt_ft: Some(5200.0), gs_kt: Some(250.0), age: 0 },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn get_trail_of_unknown_aircraft_is_not_found() {
+        let state = make_state("FLT1", vec![]);
+        let response = app(state).oneshot(get("/api/aircraft/BB2222/trail")).await.unwrap();
+        assert_eq!(response.status(), 404);
+    }
+}
@@ -0,0 +1,382 @@
+use super::AppState;
+use argon2::password_hash::PasswordHash;
+use argon2::{Argon2, PasswordVerifier};
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+
+const REALM: &str = "adsb_xgps";
+
+/// Password checks allowed at once. Each takes about 19 MiB and a good
+/// part of a core, so guesses beyond this are turned away, not queued.
+const MAX_VERIFIES: usize = 2;
+
+/// Largest form body read for its CSRF token; the forms are a few fields.
+const FORM_LIMIT: usize = 64 * 1024;
+
+/// HTTP basic login: a user name and the argon2 hash of its password, in
+/// PHC form (`$argon2id$v=19$...`).
+#[derive(Clone, Debug)]
+pub struct Login {
+    pub user: String,
+    pub hash: String,
+}
+
+/// Parse a `USER:HASH` login. PHC hashes have no colons, but user names
+/// can't have one either under basic auth, so split at the first.
+pub fn parse_login(s: &str) -> Result<Login, String> {
+    let (user, hash) = s
+        .split_once(':')
+        .ok_or_else(|| format!("'{}' should be USER:HASH", s))?;
+    if user.is_empty() {
+        return Err("the user name is empty".to_string());
+    }
+    let parsed = PasswordHash::new(hash).map_err(|e| format!("invalid password hash: {}", e))?;
+    if !parsed.algorithm.as_str().starts_with("argon2") {
+        return Err(format!("expected an argon2 hash, got {}", parsed.algorithm));
+    }
+    Ok(Login {
+        user: user.to_string(),
+        hash: hash.to_string(),
+    })
+}
+
+/// A bearer token has to be something to type, and fit in a header.
+pub fn parse_token(s: &str) -> Result<String, String> {
+    if s.is_empty() || s.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err("the token must be non-empty with no spaces".to_string());
+    }
+    Ok(s.to_string())
+}
+
+/// Who may change things through the web interface: a basic login, a
+/// bearer token, or either of the two.
+pub struct Auth {
+    login: Option<Login>,
+    token: Option<String>,
+    /// Ask for credentials on read-only pages and feeds too
+    private: bool,
+    /// Last Authorization header that passed an argon2 check. Checking is
+    /// slow by design, and the page polls every second.
+    verified: Mutex<Option<String>>,
+    /// Permits for password checks, `MAX_VERIFIES` of them
+    verifying: Arc<Semaphore>,
+}
+
+/// Outcome of checking a request's credentials.
+#[derive(Debug, PartialEq)]
+enum Access {
+    Allowed,
+    Denied,
+    /// Too many password checks running; try again shortly
+    Busy,
+}
+
+impl Auth {
+    /// None when neither a login nor a token is given.
+    pub fn new(login: Option<Login>, token: Option<String>, private: bool) -> Option<Self> {
+        if login.is_none() && token.is_none() {
+            return None;
+        }
+        Some(Auth {
+            login,
+            token,
+            private,
+            verified: Mutex::new(None),
+            verifying: Arc::new(Semaphore::new(MAX_VERIFIES)),
+        })
+    }
+
+    async fn check(&self, headers: &HeaderMap) -> Access {
+        let allowed = |ok: bool| if ok { Access::Allowed } else { Access::Denied };
+        let Some(value) = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) else {
+            return Access::Denied;
+        };
+        let (scheme, credentials) = value.split_once(' ').unwrap_or((value, ""));
+        let credentials = credentials.trim();
+        if scheme.eq_ignore_ascii_case("bearer") {
+            return allowed(
+                self.token
+                    .as_ref()
+                    .is_some_and(|token| same(token.as_bytes(), credentials.as_bytes())),
+            );
+        }
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return Access::Denied;
+        }
+        let Some(login) = &self.login else {
+            return Access::Denied;
+        };
+        if self
+            .verified
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|v| same(v.as_bytes(), value.as_bytes()))
+        {
+            return Access::Allowed;
+        }
+        let Some((user, password)) = STANDARD
+            .decode(credentials)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .and_then(|pair| pair.split_once(':').map(|(u, p)| (u.to_string(), p.to_string())))
+        else {
+            return Access::Denied;
+        };
+        // The permit goes with the check, so a client hanging up doesn't
+        // free a slot while its check still runs
+        let Ok(permit) = self.verifying.clone().try_acquire_owned() else {
+            return Access::Busy;
+        };
+        // Check the password even for the wrong user, so the time taken
+        // doesn't tell whether the name exists
+        let user_ok = same(user.as_bytes(), login.user.as_bytes());
+        let hash = login.hash.clone();
+        let password_ok = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            PasswordHash::new(&hash)
+                .is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+        })
+        .await
+        .unwrap_or(false);
+        let ok = user_ok && password_ok;
+        if ok {
+            *self.verified.lock().unwrap() = Some(value.to_string());
+        }
+        allowed(ok)
+    }
+
+    fn challenge(&self) -> Response {
+        let mut response = (StatusCode::UNAUTHORIZED, "Login required").into_response();
+        let headers = response.headers_mut();
+        if self.login.is_some() {
+            let value = format!("Basic realm=\"{}\", charset=\"UTF-8\"", REALM);
+            headers.append(header::WWW_AUTHENTICATE, value.parse().unwrap());
+        }
+        if self.token.is_some() {
+            let value = format!("Bearer realm=\"{}\"", REALM);
+            headers.append(header::WWW_AUTHENTICATE, value.parse().unwrap());
+        }
+        response
+    }
+}
+
+/// Compare without stopping at the first difference.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn is_read(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD)
+}
+
+/// Ask for credentials on anything that changes state, and on everything
+/// else too when the interface is private.
+pub(super) async fn require_login(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(auth) = &state.auth else {
+        return next.run(request).await;
+    };
+    if is_read(request.method()) && !auth.private {
+        return next.run(request).await;
+    }
+    match auth.check(request.headers()).await {
+        Access::Allowed => next.run(request).await,
+        Access::Denied => auth.challenge(),
+        Access::Busy => {
+            let mut response =
+                (StatusCode::SERVICE_UNAVAILABLE, "Too many login attempts; try again").into_response();
+            response.headers_mut().insert(header::RETRY_AFTER, "1".parse().unwrap());
+            response
+        }
+    }
+}
+
+/// Random token for this run, hex encoded so it needs no escaping in a
+/// form body, an attribute or a script.
+pub(super) fn new_csrf_token() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("the OS random source is available");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hidden field carrying the CSRF token, for every form that posts back.
+pub(super) fn csrf_input(token: &str) -> String {
+    format!(r#"<input type="hidden" name="csrf" value="{}">"#, token)
+}
+
+/// Turn away POSTs without this run's CSRF token. Every POST route is an
+/// HTML form; the JSON API uses PUT and DELETE with a JSON body, which
+/// another site can't send without the browser asking us first.
+pub(super) async fn check_csrf(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    if request.method() != Method::POST {
+        return next.run(request).await;
+    }
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, FORM_LIMIT).await else {
+        return (StatusCode::PAYLOAD_TOO_LARGE, "Form too large").into_response();
+    };
+    let expected = format!("csrf={}", state.csrf);
+    if !bytes.split(|b| *b == b'&').any(|pair| same(pair, expected.as_bytes())) {
+        return (StatusCode::FORBIDDEN, "Form expired or not from this page; reload and try again").into_response();
+    }
+    next.run(Request::from_parts(parts, Body::from(bytes))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::app;
+    use super::super::tests::{make_state, response_body, TEST_CSRF};
+    use super::*;
+    use argon2::password_hash::{PasswordHasher, SaltString};
+
+    fn hash(password: &str) -> String {
+        let salt = SaltString::encode_b64(b"adsb_xgps test salt").unwrap();
+        Argon2::default().hash_password(password.as_bytes(), &salt).unwrap().to_string()
+    }
+
+    fn with_auth(auth: Option<Auth>) -> Arc<AppState> {
+        let mut state = Arc::try_unwrap(make_state("FLT1", vec![])).ok().unwrap();
+        state.auth = auth;
+        Arc::new(state)
+    }
+
+    async fn send(state: Arc<AppState>, method: &str, uri: &str, authorization: Option<&str>, body: &str) -> Response {
+        use tower::ServiceExt;
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/x-www-form-urlencoded");
+        if let Some(value) = authorization {
+            request = request.header("authorization", value);
+        }
+        app(state).oneshot(request.body(Body::from(body.to_string())).unwrap()).await.unwrap()
+    }
+
+    fn basic(user: &str, password: &str) -> String {
+        format!("Basic {}", STANDARD.encode(format!("{}:{}", user, password)))
+    }
+
+    #[test]
+    fn parses_logins() {
+        let login = parse_login(&format!("admin:{}", hash("secret"))).unwrap();
+        assert_eq!(login.user, "admin");
+        assert!(login.hash.starts_with("$argon2id$"));
+        assert!(parse_login("admin").is_err());
+        assert!(parse_login(":$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA").is_err());
+        assert!(parse_login("admin:secret").is_err());
+        assert_eq!(parse_token("s3cret").unwrap(), "s3cret");
+        assert!(parse_token("").is_err());
+        assert!(parse_token("two words").is_err());
+    }
+
+    #[tokio::test]
+    async fn basic_login_guards_changes() {
+        let login = parse_login(&format!("admin:{}", hash("secret"))).unwrap();
+        let state = with_auth(Auth::new(Some(login), None, false));
+        let form = format!("target=NEW1&csrf={}", TEST_CSRF);
+
+        let response = send(state.clone(), "POST", "/track", None, &form).await;
+        assert_eq!(response.status(), 401);
+        assert_eq!(response.headers()["www-authenticate"], "Basic realm=\"adsb_xgps\", charset=\"UTF-8\"");
+        let wrong = basic("admin", "guess");
+        assert_eq!(send(state.clone(), "POST", "/track", Some(&wrong), &form).await.status(), 401);
+        let wrong_user = basic("root", "secret");
+        assert_eq!(send(state.clone(), "POST", "/track", Some(&wrong_user), &form).await.status(), 401);
+        assert_eq!(state.slots[0].tracker.read().await.target.to_string(), "FLT1");
+
+        let right = basic("admin", "secret");
+        assert_eq!(send(state.clone(), "POST", "/track", Some(&right), &form).await.status(), 303);
+        assert_eq!(state.slots[0].tracker.read().await.target.to_string(), "NEW1");
+        // Remembered after the first check
+        assert!(state.auth.as_ref().unwrap().verified.lock().unwrap().is_some());
+        assert_eq!(send(state.clone(), "POST", "/lock", Some(&right), &form).await.status(), 303);
+
+        assert_eq!(send(state.clone(), "GET", "/", None, "").await.status(), 200);
+        assert_eq!(send(state, "GET", "/data", None, "").await.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn password_checks_are_limited() {
+        let login = parse_login(&format!("admin:{}", hash("secret"))).unwrap();
+        let state = with_auth(Auth::new(Some(login), None, false));
+        let form = format!("target=NEW1&csrf={}", TEST_CSRF);
+        let right = basic("admin", "secret");
+        assert_eq!(send(state.clone(), "POST", "/track", Some(&right), &form).await.status(), 303);
+
+        let auth = state.auth.as_ref().unwrap();
+        let _running = auth.verifying.clone().try_acquire_many_owned(MAX_VERIFIES as u32).unwrap();
+        let response = send(state.clone(), "POST", "/track", Some(&basic("admin", "guess")), &form).await;
+        assert_eq!(response.status(), 503);
+        assert_eq!(response.headers()["retry-after"], "1");
+        // A login already checked needs no new check
+        assert_eq!(send(state.clone(), "POST", "/lock", Some(&right), &form).await.status(), 303);
+        assert_eq!(send(state, "POST", "/lock", Some("Bearer s3cret"), &form).await.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn bearer_token_guards_api() {
+        let state = with_auth(Auth::new(None, Some("s3cret".to_string()), false));
+        let request = |authorization: Option<&str>| {
+            let mut request = Request::builder().method("DELETE").uri("/api/track");
+            if let Some(value) = authorization {
+                request = request.header("authorization", value);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+        use tower::ServiceExt;
+        let response = app(state.clone()).oneshot(request(None)).await.unwrap();
+        assert_eq!(response.status(), 401);
+        assert_eq!(response.headers()["www-authenticate"], "Bearer realm=\"adsb_xgps\"");
+        let response = app(state.clone()).oneshot(request(Some("Bearer nope"))).await.unwrap();
+        assert_eq!(response.status(), 401);
+        let response = app(state).oneshot(request(Some("Bearer s3cret"))).await.unwrap();
+        assert_eq!(response.status(), 204);
+    }
+
+    #[tokio::test]
+    async fn private_guards_reads() {
+        let state = with_auth(Auth::new(None, Some("s3cret".to_string()), true));
+        assert_eq!(send(state.clone(), "GET", "/", None, "").await.status(), 401);
+        assert_eq!(send(state.clone(), "GET", "/data", None, "").await.status(), 401);
+        assert_eq!(send(state, "GET", "/data", Some("Bearer s3cret"), "").await.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn forms_need_csrf_token() {
+        let state = make_state("FLT1", vec![]);
+        let response = send(state.clone(), "POST", "/track", None, "target=NEW1").await;
+        assert_eq!(response.status(), 403);
+        assert!(response_body(response).await.contains("reload"));
+        assert_eq!(send(state.clone(), "POST", "/lock", None, "csrf=wrong").await.status(), 403);
+        assert_eq!(state.slots[0].tracker.read().await.target.to_string(), "FLT1");
+        assert!(!state.slots[0].tracker.read().await.lock);
+
+        let form = format!("target=NEW1&csrf={}", TEST_CSRF);
+        assert_eq!(send(state.clone(), "POST", "/track", None, &form).await.status(), 303);
+        assert_eq!(state.slots[0].tracker.read().await.target.to_string(), "NEW1");
+    }
+
+    #[tokio::test]
+    async fn pages_carry_csrf_token() {
+        let state = make_state("FLT1", vec![]);
+        let body = response_body(send(state, "GET", "/", None, "").await).await;
+        let field = csrf_input(TEST_CSRF);
+        // The queue form, the slot's lock form and the page script
+        assert!(body.matches(&field).count() >= 3);
+    }
+
+    #[test]
+    fn tokens_are_random_hex() {
+        let a = new_csrf_token();
+        assert_eq!(a.len(), 32);
+        assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a, new_csrf_token());
+    }
+}
@@ -0,0 +1,351 @@
+use super::{aircraft_entry, auth, escape_html, view_slots, AppState};
+use crate::Aircraft;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use std::fmt::Write;
+use std::sync::Arc;
+use tokio::time::Instant;
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/aircraft/{hex}", get(get_detail))
+}
+
+/// What each SBS transmission type carries, MSG,1 first.
+const MESSAGE_TYPES: [&str; 8] = [
+    "identification",
+    "surface position",
+    "airborne position",
+    "airborne velocity",
+    "surveillance altitude",
+    "surveillance ID (squawk)",
+    "air-to-air",
+    "all-call reply",
+];
+
+const SPARK_WIDTH: f64 = 300.0;
+const SPARK_HEIGHT: f64 = 40.0;
+const PLOT_SIZE: f64 = 300.0;
+
+/// Seconds since a field was last reported, blank if it never was.
+fn field_age(updated: Option<Instant>) -> String {
+    updated.map_or(String::new(), |t| format!("{}s", t.elapsed().as_secs()))
+}
+
+fn or_dash<T: ToString>(value: Option<T>) -> String {
+    value.map_or("-".to_string(), |v| v.to_string())
+}
+
+/// Line of `(seconds ago, value)` samples, oldest on the left, labelled with
+/// the range and latest value.
+fn sparkline(samples: &[(f64, f64)], unit: &str) -> String {
+    if samples.len() < 2 {
+        return "not enough data".to_string();
+    }
+    let span = samples.iter().map(|s| s.0).fold(0.0, f64::max).max(1.0);
+    let min = samples.iter().map(|s| s.1).fold(f64::INFINITY, f64::min);
+    let max = samples.iter().map(|s| s.1).fold(f64::NEG_INFINITY, f64::max);
+    let range = (max - min).max(1.0);
+    let points: Vec<String> = samples
+        .iter()
+        .map(|(age, v)| {
+            format!(
+                "{:.1},{:.1}",
+                (span - age) / span * SPARK_WIDTH,
+                SPARK_HEIGHT - 2.0 - (v - min) / range * (SPARK_HEIGHT - 4.0)
+            )
+        })
+        .collect();
+    let last = samples[samples.len() - 1].1;
+    format!(
+        "<svg class=\"spark\" viewBox=\"0 0 {w} {h}\" width=\"{w}\" height=\"{h}\"><polyline points=\"{}\"/></svg> \
+{last:.0} {unit} (range {min:.0}&ndash;{max:.0} over {span:.0}s)",
+        points.join(" "),
+        w = SPARK_WIDTH,
+        h = SPARK_HEIGHT,
+    )
+}
+
+/// The trail drawn to fit a square, north up, with every point marked so
+/// stray positions stand out.
+fn trail_plot(a: &Aircraft) -> String {
+    let points: Vec<(f64, f64)> = a.trail.iter().map(|p| (p.latitude, p.longitude)).collect();
+    if points.len() < 2 {
+        return "<p>Not enough positions to draw.</p>".to_string();
+    }
+    let min_lat = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let max_lat = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+    let min_lon = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let max_lon = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+    let cos_lat = ((min_lat + max_lat) / 2.0).to_radians().cos();
+    let extent = (max_lat - min_lat).max((max_lon - min_lon) * cos_lat).max(1e-4);
+    let scale = (PLOT_SIZE - 20.0) / extent;
+    let project = |(lat, lon): (f64, f64)| {
+        (
+            10.0 + (lon - min_lon) * cos_lat * scale,
+            PLOT_SIZE - 10.0 - (lat - min_lat) * scale,
+        )
+    };
+
+    let mut svg = format!(
+        "<svg class=\"plot\" viewBox=\"0 0 {s} {s}\" width=\"{s}\" height=\"{s}\"><polyline points=\"",
+        s = PLOT_SIZE
+    );
+    for &p in &points {
+        let (x, y) = project(p);
+        let _ = write!(svg, "{:.1},{:.1} ", x, y);
+    }
+    svg.push_str("\"/>");
+    for &p in &points {
+        let (x, y) = project(p);
+        let _ = write!(svg, "<circle r=\"1.5\" cx=\"{:.1}\" cy=\"{:.1}\"/>", x, y);
+    }
+    let (x, y) = project(points[points.len() - 1]);
+    let _ = write!(svg, "<circle class=\"last\" r=\"4\" cx=\"{:.1}\" cy=\"{:.1}\"/></svg>", x, y);
+    svg
+}
+
+fn detail_page(hex: &str, a: &Aircraft, slots: &[String], slot_names: &[&str], csrf: &str) -> String {
+    let callsign = a.callsign.as_deref().unwrap_or("");
+    let title = if callsign.is_empty() {
+        hex.to_string()
+    } else {
+        format!("{} {}", hex, callsign)
+    };
+
+    let fields = [
+        ("Callsign", or_dash(a.callsign.as_ref()), a.updated.callsign),
+        ("Squawk", or_dash(a.squawk.as_ref()), a.updated.squawk),
+        ("Latitude", or_dash(a.latitude.map(|v| format!("{:.5}", v))), a.updated.position),
+        ("Longitude", or_dash(a.longitude.map(|v| format!("{:.5}", v))), a.updated.position),
+        ("Altitude (ft)", or_dash(a.altitude_ft), a.updated.altitude_ft),
+        ("Ground speed (kt)", or_dash(a.ground_speed_kt), a.updated.ground_speed_kt),
+        ("Track", or_dash(a.track), a.updated.track),
+        ("Vertical rate (fpm)", or_dash(a.vertical_rate_fpm), a.updated.vertical_rate_fpm),
+        ("On ground", or_dash(a.on_ground.map(|g| if g { "yes" } else { "no" })), a.updated.on_ground),
+    ];
+    let mut field_rows = String::new();
+    for (label, value, updated) in &fields {
+        let _ = writeln!(
+            field_rows,
+            "<tr><th>{}</th><td>{}</td><td class=\"r\">{}</td></tr>",
+            label,
+            escape_html(value),
+            field_age(*updated)
+        );
+    }
+    let _ = writeln!(
+        field_rows,
+        "<tr><th>Last heard</th><td></td><td class=\"r\">{}s</td></tr>",
+        a.last_updated.elapsed().as_secs()
+    );
+
+    let lookup = match (&a.registration, &a.type_code) {
+        (None, None) => "<p>Not in the aircraft database.</p>".to_string(),
+        (reg, type_code) => format!(
+            "<table><tr><th>Registration</th><td>{}</td></tr><tr><th>Type</th><td>{}</td></tr></table>",
+            escape_html(&or_dash(reg.as_ref())),
+            escape_html(&or_dash(type_code.as_ref()))
+        ),
+    };
+
+    let mut message_rows = String::new();
+    for (i, (name, count)) in MESSAGE_TYPES.iter().zip(a.messages).enumerate() {
+        let _ = writeln!(
+            message_rows,
+            "<tr><td>MSG,{}</td><td>{}</td><td class=\"r\">{}</td></tr>",
+            i + 1,
+            name,
+            count
+        );
+    }
+    let _ = writeln!(
+        message_rows,
+        "<tr><th colspan=\"2\">Total</th><td class=\"r\">{}</td></tr>",
+        a.messages.iter().sum::<u64>()
+    );
+
+    let ages: Vec<f64> = a.trail.iter().map(|p| p.at.elapsed().as_secs_f64()).collect();
+    let altitudes: Vec<(f64, f64)> = a
+        .trail
+        .iter()
+        .zip(&ages)
+        .filter_map(|(p, &age)| p.altitude_ft.map(|v| (age, v)))
+        .collect();
+    let speeds: Vec<(f64, f64)> = a
+        .trail
+        .iter()
+        .zip(&ages)
+        .filter_map(|(p, &age)| p.ground_speed_kt.map(|v| (age, v)))
+        .collect();
+
+    let tracking = if slots.is_empty() {
+        "not tracked".to_string()
+    } else {
+        format!("tracked in {}", escape_html(&slots.join(", ")))
+    };
+    let slot_select = if slot_names.len() > 1 {
+        let options: String = slot_names
+            .iter()
+            .map(|s| format!("<option>{}</option>", escape_html(s)))
+            .collect();
+        format!("<select name=\"slot\">{}</select>", options)
+    } else {
+        String::new()
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="5">
+<title>{title} - adsb_xgps</title>
+<style>
+body {{ font-family: monospace; background: #1a1a2e; color: #e0e0e0; margin: 20px; }}
+h1, h2 {{ color: #00d4ff; }}
+a {{ color: #00d4ff; }}
+table {{ border-collapse: collapse; margin-bottom: 10px; }}
+th, td {{ border: 1px solid #333; padding: 6px 10px; text-align: left; }}
+th {{ background: #16213e; color: #00d4ff; }}
+.r {{ text-align: right; }}
+button {{ background: #00d4ff; color: #1a1a2e; border: none; padding: 3px 10px; cursor: pointer; font-family: monospace; }}
+svg {{ background: #10101e; border: 1px solid #333; vertical-align: middle; }}
+svg polyline {{ fill: none; stroke: #33ff66; }}
+svg circle {{ fill: #33ff66; }}
+svg circle.last {{ fill: #fff; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p><a href="/">Back</a> &middot; {tracking}</p>
+<form method="POST" action="/track"><input type="hidden" name="target" value="hex:{hex}">{slot_select}{csrf_input}<button type="submit">Track this</button></form>
+<h2>State</h2>
+<table>
+<thead><tr><th>Field</th><th>Value</th><th>Age</th></tr></thead>
+<tbody>
+{field_rows}</tbody>
+</table>
+<h2>Registration and type</h2>
+{lookup}
+<h2>Messages</h2>
+<table>
+<thead><tr><th>Type</th><th>Carries</th><th>Count</th></tr></thead>
+<tbody>
+{message_rows}</tbody>
+</table>
+<h2>Trail</h2>
+<p>{trail_len} positions &middot; <a href="/api/aircraft/{hex}/trail">JSON</a></p>
+{plot}
+<p>Altitude: {altitude}</p>
+<p>Speed: {speed}</p>
+</body>
+</html>"#,
+        title = escape_html(&title),
+        hex = escape_html(hex),
+        trail_len = a.trail.iter().count(),
+        plot = trail_plot(a),
+        altitude = sparkline(&altitudes, "ft"),
+        speed = sparkline(&speeds, "kt"),
+        csrf_input = auth::csrf_input(csrf),
+    )
+}
+
+async fn get_detail(State(state): State<Arc<AppState>>, Path(hex): Path<String>) -> Response {
+    let hex = hex.to_ascii_uppercase();
+    let map = state.aircraft_map.read().await;
+    let Some(aircraft) = map.get(&hex) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Html(format!(
+                "<!DOCTYPE html><html><body><p>No aircraft {}. <a href=\"/\">Back</a></p></body></html>",
+                escape_html(&hex)
+            )),
+        )
+            .into_response();
+    };
+    let views = view_slots(&state.slots, &map).await;
+    let entry = aircraft_entry(&hex, aircraft, &views);
+    let slot_names: Vec<&str> = state.slots.iter().map(|s| s.name.as_str()).collect();
+    Html(detail_page(&hex, aircraft, &entry.slots, &slot_names, &state.csrf)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::app;
+    use super::super::tests::{make_aircraft, make_slots_state, make_state, response_body};
+    use super::*;
+    use crate::trail::TrailPoint;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use tower::ServiceExt;
+
+    async fn get(state: Arc<AppState>, uri: &str) -> Response {
+        app(state)
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn sparkline_spans_samples() {
+        assert_eq!(sparkline(&[(0.0, 100.0)], "kt"), "not enough data");
+        let line = sparkline(&[(10.0, 1000.0), (5.0, 2000.0), (0.0, 1500.0)], "ft");
+        assert!(line.contains("<polyline points=\"0.0,38.0 150.0,2.0 300.0,20.0\"/>"));
+        assert!(line.ends_with("1500 ft (range 1000&ndash;2000 over 10s)"));
+    }
+
+    #[tokio::test]
+    async fn detail_page_shows_airframe() {
+        let mut a = make_aircraft(Some("FLT1"));
+        a.messages = [1, 0, 12, 5, 0, 0, 0, 0];
+        a.updated.altitude_ft = Some(Instant::now());
+        for (lat, alt) in [(40.0, 5000.0), (40.01, 5200.0)] {
+            a.trail.push(TrailPoint {
+                latitude: lat,
+                longitude: -74.0,
+                altitude_ft: Some(alt),
+                ground_speed_kt: Some(250.0),
+                at: Instant::now(),
+            });
+        }
+        let state = make_state("FLT1", vec![("AA1111", a)]);
+        let response = get(state, "/aircraft/aa1111").await;
+
+        assert_eq!(response.status(), 200);
+        let body = response_body(response).await;
+        assert!(body.contains("<h1>AA1111 FLT1</h1>"));
+        assert!(body.contains("tracked in main"));
+        assert!(body.contains("<tr><th>Altitude (ft)</th><td>35000</td><td class=\"r\">0s</td></tr>"));
+        assert!(body.contains("<tr><th>Squawk</th><td>-</td><td class=\"r\"></td></tr>"));
+        assert!(body.contains("<tr><td>MSG,3</td><td>airborne position</td><td class=\"r\">12</td></tr>"));
+        assert!(body.contains("<tr><th colspan=\"2\">Total</th><td class=\"r\">18</td></tr>"));
+        assert!(body.contains("Not in the aircraft database."));
+        assert!(body.contains("2 positions"));
+        assert!(body.contains("<svg class=\"plot\""));
+        assert!(body.contains("<input type=\"hidden\" name=\"target\" value=\"hex:AA1111\">"));
+        assert!(!body.contains("<select name=\"slot\">"));
+    }
+
+    #[tokio::test]
+    async fn detail_page_shows_lookup_and_slot_choice() {
+        let mut a = make_aircraft(Some("FLT1"));
+        a.registration = Some("N12345".to_string());
+        a.type_code = Some("B738".to_string());
+        let state = make_slots_state(&[("main", "FLT9"), ("second", "FLT8")], vec![("AA1111", a)]);
+        let body = response_body(get(state, "/aircraft/AA1111").await).await;
+
+        assert!(body.contains("<tr><th>Registration</th><td>N12345</td></tr><tr><th>Type</th><td>B738</td></tr>"));
+        assert!(body.contains("not tracked"));
+        assert!(body.contains("<select name=\"slot\"><option>main</option><option>second</option></select>"));
+        assert!(body.contains("Not enough positions to draw."));
+    }
+
+    #[tokio::test]
+    async fn unknown_aircraft_is_not_found() {
+        let state = make_state("FLT1", vec![]);
+        assert_eq!(get(state, "/aircraft/BB2222").await.status(), 404);
+    }
+}
@@ -0,0 +1,376 @@
+use super::{collect_data, AircraftEntry, AppState, DataResponse, DestinationEntry, SlotEntry};
+use crate::xgps::MainFix;
+use axum::extract::{Request, State};
+use axum::http::Method;
+use axum::middleware::Next;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use futures_util::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/events", get(get_events))
+}
+
+/// Aircraft that changed since the last push. Ages growing don't count as
+/// a change, clients age entries locally; an age going down means the
+/// aircraft was heard again and does.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct AircraftDiff {
+    updated: Vec<AircraftEntry>,
+    removed: Vec<String>,
+}
+
+/// One message on the event stream, named after its SSE event type.
+#[derive(Debug, PartialEq)]
+enum Update {
+    /// Full state, sent first on every connection
+    Snapshot(Box<DataResponse>),
+    Aircraft(AircraftDiff),
+    /// Tracking state of every slot, sent whenever any of it changes
+    Slots(Vec<SlotEntry>),
+    Destinations(Vec<DestinationEntry>),
+}
+
+impl Update {
+    fn into_event(self) -> Event {
+        let (name, data) = match self {
+            Update::Snapshot(d) => ("snapshot", serde_json::to_string(&d)),
+            Update::Aircraft(d) => ("aircraft", serde_json::to_string(&d)),
+            Update::Slots(d) => ("slots", serde_json::to_string(&d)),
+            Update::Destinations(d) => ("destinations", serde_json::to_string(&d)),
+        };
+        Event::default()
+            .event(name)
+            .data(data.expect("web data always serializes"))
+    }
+}
+
+/// What the client last saw, to diff the next state against.
+struct Seen {
+    aircraft: HashMap<String, AircraftEntry>,
+    slots: Vec<SlotEntry>,
+    destinations: Vec<DestinationEntry>,
+}
+
+impl Seen {
+    fn new(data: &DataResponse) -> Self {
+        Seen {
+            aircraft: data
+                .aircraft
+                .iter()
+                .map(|a| (a.hex.clone(), a.clone()))
+                .collect(),
+            slots: data.slots.clone(),
+            destinations: data.destinations.clone(),
+        }
+    }
+
+    /// Updates that bring the client from what it saw to `data`.
+    fn diff(&mut self, data: &DataResponse) -> Vec<Update> {
+        let mut updates = Vec::new();
+
+        let mut diff = AircraftDiff::default();
+        for entry in &data.aircraft {
+            // Anything heard again goes out, even with the same fields, so
+            // the page's age restarts from zero
+            let changed = self
+                .aircraft
+                .get(&entry.hex)
+                .is_none_or(|seen| {
+                    entry.age < seen.age
+                        || entry.heard != seen.heard
+                        || seen != &AircraftEntry { age: seen.age, ..entry.clone() }
+                });
+            if changed {
+                diff.updated.push(entry.clone());
+            }
+        }
+        let current: HashSet<&str> = data.aircraft.iter().map(|a| a.hex.as_str()).collect();
+        diff.removed = self
+            .aircraft
+            .keys()
+            .filter(|hex| !current.contains(hex.as_str()))
+            .cloned()
+            .collect();
+        diff.removed.sort();
+        if diff != AircraftDiff::default() {
+            updates.push(Update::Aircraft(diff));
+        }
+        if data.slots != self.slots {
+            updates.push(Update::Slots(data.slots.clone()));
+        }
+        if data.destinations != self.destinations {
+            updates.push(Update::Destinations(data.destinations.clone()));
+        }
+
+        *self = Seen::new(data);
+        updates
+    }
+}
+
+/// Publish the state for the event stream on every broadcaster tick, and
+/// straight after changes made through the web. Skipped while nobody is
+/// connected.
+pub(super) async fn publish(state: Arc<AppState>, mut ticks: MainFix) {
+    loop {
+        tokio::select! {
+            changed = ticks.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+            _ = state.refresh.notified() => {}
+        }
+        if state.snapshots.receiver_count() == 0 {
+            continue;
+        }
+        let data = collect_data(&state).await;
+        state.snapshots.send_replace(Some(Arc::new(data)));
+    }
+}
+
+/// Wake `publish` once a request that may have changed something is done.
+pub(super) async fn refresh_after_change(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let change = !matches!(*request.method(), Method::GET | Method::HEAD);
+    let response = next.run(request).await;
+    if change {
+        state.refresh.notify_one();
+    }
+    response
+}
+
+struct Connection {
+    snapshots: watch::Receiver<Option<Arc<DataResponse>>>,
+    seen: Seen,
+    pending: VecDeque<Update>,
+}
+
+async fn get_events(
+    State(state): State<Arc<AppState>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // Subscribe first, so nothing published after the opening snapshot is missed
+    let snapshots = state.snapshots.subscribe();
+    let data = collect_data(&state).await;
+    let connection = Connection {
+        snapshots,
+        seen: Seen::new(&data),
+        pending: VecDeque::from([Update::Snapshot(Box::new(data))]),
+    };
+
+    let stream = stream::unfold(connection, |mut conn| async move {
+        loop {
+            if let Some(update) = conn.pending.pop_front() {
+                return Some((Ok(update.into_event()), conn));
+            }
+            conn.snapshots.changed().await.ok()?;
+            let data = conn.snapshots.borrow_and_update().clone();
+            if let Some(data) = data {
+                conn.pending.extend(conn.seen.diff(&data));
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::app;
+    use super::super::tests::{make_aircraft, make_state, TEST_CSRF};
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    fn entry(hex: &str, alt: f64, age: u64) -> AircraftEntry {
+        AircraftEntry {
+            hex: hex.to_string(),
+            callsign: "FLT1".to_string(),
+            registration: None,
+            type_code: None,
+            squawk: None,
+            lat: Some(40.0),
+            lon: Some(-74.0),
+            alt_ft: Some(alt),
+            gs_kt: None,
+            track: None,
+            vs_fpm: None,
+            on_ground: None,
+            age,
+            tracking: false,
+            matched: false,
+            slots: Vec::new(),
+            heard: None,
+        }
+    }
+
+    fn data(aircraft: Vec<AircraftEntry>) -> DataResponse {
+        DataResponse {
+            receiver: None,
+            main: None,
+            slots: Vec::new(),
+            aircraft,
+            destinations: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn unchanged_state_sends_nothing() {
+        let mut seen = Seen::new(&data(vec![entry("AA1111", 5000.0, 0)]));
+        assert!(seen.diff(&data(vec![entry("AA1111", 5000.0, 3)])).is_empty());
+    }
+
+    #[test]
+    fn heard_again_is_a_change() {
+        let mut seen = Seen::new(&data(vec![entry("AA1111", 5000.0, 4)]));
+        assert_eq!(
+            seen.diff(&data(vec![entry("AA1111", 5000.0, 0)])),
+            vec![Update::Aircraft(AircraftDiff {
+                updated: vec![entry("AA1111", 5000.0, 0)],
+                removed: Vec::new(),
+            })]
+        );
+    }
+
+    #[test]
+    fn heard_again_unchanged_is_resent() {
+        // Heard on consecutive ticks at the same level: age stays 0
+        let at = tokio::time::Instant::now();
+        let heard = |secs| AircraftEntry {
+            heard: Some(at + std::time::Duration::from_secs(secs)),
+            ..entry("AA1111", 35000.0, 0)
+        };
+        let mut seen = Seen::new(&data(vec![heard(0)]));
+        assert_eq!(
+            seen.diff(&data(vec![heard(1)])),
+            vec![Update::Aircraft(AircraftDiff {
+                updated: vec![heard(1)],
+                removed: Vec::new(),
+            })]
+        );
+        assert!(seen.diff(&data(vec![heard(1)])).is_empty());
+    }
+
+    #[test]
+    fn diff_lists_changed_new_and_removed_aircraft() {
+        let mut seen = Seen::new(&data(vec![
+            entry("AA1111", 5000.0, 0),
+            entry("BB2222", 6000.0, 0),
+            entry("CC3333", 7000.0, 0),
+        ]));
+        let updates = seen.diff(&data(vec![
+            entry("AA1111", 5100.0, 0),
+            entry("BB2222", 6000.0, 1),
+            entry("DD4444", 8000.0, 0),
+        ]));
+
+        assert_eq!(
+            updates,
+            vec![Update::Aircraft(AircraftDiff {
+                updated: vec![entry("AA1111", 5100.0, 0), entry("DD4444", 8000.0, 0)],
+                removed: vec!["CC3333".to_string()],
+            })]
+        );
+        assert!(seen.diff(&data(vec![
+            entry("AA1111", 5100.0, 0),
+            entry("BB2222", 6000.0, 1),
+            entry("DD4444", 8000.0, 0),
+        ]))
+        .is_empty());
+    }
+
+    #[test]
+    fn diff_reports_slot_changes() {
+        let mut before = data(Vec::new());
+        before.slots.push(SlotEntry {
+            name: "main".to_string(),
+            tracked: "FLT1".to_string(),
+            conflict: Vec::new(),
+            matched: Vec::new(),
+            chosen: None,
+            rule: "freshest".to_string(),
+            lock: false,
+            locked: None,
+            status: "acquiring".to_string(),
+            lost_secs: None,
+            until: Vec::new(),
+            watch: Vec::new(),
+        });
+        let mut seen = Seen::new(&before);
+        let mut after = data(Vec::new());
+        after.slots = before.slots.clone();
+        after.slots[0].chosen = Some("AA1111".to_string());
+
+        assert_eq!(seen.diff(&after), vec![Update::Slots(after.slots)]);
+    }
+
+    #[tokio::test]
+    async fn stream_starts_with_snapshot() {
+        let state = make_state("FLT1", vec![("AA1111", make_aircraft(Some("FLT1")))]);
+        let response = app(state)
+            .oneshot(Request::builder().uri("/events").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let content_type = response.headers().get("content-type").unwrap().to_str().unwrap();
+        assert_eq!(content_type, "text/event-stream");
+
+        let mut body = response.into_body();
+        let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+        let text = String::from_utf8(frame.to_vec()).unwrap();
+        assert!(text.starts_with("event: snapshot\ndata: "));
+        let json = text
+            .trim_start_matches("event: snapshot\ndata: ")
+            .trim_end();
+        let snapshot: DataResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(snapshot.aircraft[0].hex, "AA1111");
+        assert_eq!(snapshot.slots[0].chosen.as_deref(), Some("AA1111"));
+    }
+
+    async fn next_event(body: &mut Body) -> String {
+        let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+        String::from_utf8(frame.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn ticks_and_web_changes_are_pushed() {
+        let state = make_state("FLT1", vec![("AA1111", make_aircraft(Some("FLT1")))]);
+        let (ticks, tick_rx) = watch::channel(None);
+        let publisher = tokio::spawn(publish(state.clone(), tick_rx));
+        let response = app(state.clone())
+            .oneshot(Request::builder().uri("/events").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let mut body = response.into_body();
+        assert!(next_event(&mut body).await.starts_with("event: snapshot
+"));
+
+        state.aircraft_map.write().await.get_mut("AA1111").unwrap().altitude_ft = Some(12000.0);
+        ticks.send_replace(None);
+        let text = next_event(&mut body).await;
+        assert!(text.starts_with("event: aircraft
+"), "{}", text);
+        assert!(text.contains("12000"));
+
+        // No tick needed for a change made through the web
+        let lock = Request::builder()
+            .method("POST")
+            .uri("/lock")
+            .body(Body::from(format!("csrf={}", TEST_CSRF)))
+            .unwrap();
+        assert_eq!(app(state.clone()).oneshot(lock).await.unwrap().status(), 303);
+        let text = next_event(&mut body).await;
+        assert!(text.starts_with("event: slots
+"), "{}", text);
+        assert!(text.contains("\"lock\":true"));
+        publisher.abort();
+    }
+}
@@ -0,0 +1,294 @@
+use super::api::ApiError;
+use super::{aircraft_entry, view_slots, AppState};
+use crate::Aircraft;
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/api/aircraft.geojson", get(get_geojson))
+}
+
+/// Filters for `/api/aircraft.geojson`. They apply to each aircraft's
+/// current position; a matching aircraft brings its whole trail.
+#[derive(Debug, Default, Deserialize)]
+struct GeoJsonQuery {
+    /// Add each aircraft's trail as a LineString
+    #[serde(default)]
+    trails: bool,
+    /// MIN_LON,MIN_LAT,MAX_LON,MAX_LAT, as in GeoJSON; MIN_LON above
+    /// MAX_LON crosses the antimeridian
+    bbox: Option<String>,
+    /// Feet; aircraft without an altitude are left out when either is set
+    min_alt: Option<f64>,
+    max_alt: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Bbox {
+    min_lon: f64,
+    min_lat: f64,
+    max_lon: f64,
+    max_lat: f64,
+}
+
+impl Bbox {
+    fn parse(s: &str) -> Result<Self, String> {
+        let values: Vec<f64> = s
+            .split(',')
+            .map(|v| v.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("invalid bbox '{}'", s))?;
+        let [min_lon, min_lat, max_lon, max_lat] = values[..] else {
+            return Err(format!("bbox needs four values, got '{}'", s));
+        };
+        if min_lat > max_lat {
+            return Err(format!("bbox minimum latitude is above its maximum in '{}'", s));
+        }
+        Ok(Bbox {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        })
+    }
+
+    fn contains(&self, lat: f64, lon: f64) -> bool {
+        // RFC 7946 5.2: a box across the antimeridian has west above east
+        let lon_inside = if self.min_lon <= self.max_lon {
+            (self.min_lon..=self.max_lon).contains(&lon)
+        } else {
+            lon >= self.min_lon || lon <= self.max_lon
+        };
+        (self.min_lat..=self.max_lat).contains(&lat) && lon_inside
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+struct FeatureCollection {
+    features: Vec<Feature>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+struct Feature {
+    id: String,
+    geometry: Geometry,
+    properties: serde_json::Value,
+}
+
+/// Coordinates are `[lon, lat]`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum Geometry {
+    Point { coordinates: [f64; 2] },
+    LineString { coordinates: Vec<[f64; 2]> },
+}
+
+fn passes(a: &Aircraft, lat: f64, lon: f64, query: &GeoJsonQuery, bbox: Option<&Bbox>) -> bool {
+    if bbox.is_some_and(|b| !b.contains(lat, lon)) {
+        return false;
+    }
+    if query.min_alt.is_none() && query.max_alt.is_none() {
+        return true;
+    }
+    a.altitude_ft.is_some_and(|alt| {
+        query.min_alt.is_none_or(|min| alt >= min) && query.max_alt.is_none_or(|max| alt <= max)
+    })
+}
+
+async fn get_geojson(
+    State(state): State<Arc<AppState>>,
+    query: Result<Query<GeoJsonQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Query(query) = query?;
+    let bbox = query
+        .bbox
+        .as_deref()
+        .map(Bbox::parse)
+        .transpose()
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+
+    let map = state.aircraft_map.read().await;
+    let views = view_slots(&state.slots, &map).await;
+    let mut hexes: Vec<&String> = map.keys().collect();
+    hexes.sort();
+
+    let mut features = Vec::new();
+    for hex in hexes {
+        let a = &map[hex];
+        let (Some(lat), Some(lon)) = (a.latitude, a.longitude) else {
+            continue;
+        };
+        if !passes(a, lat, lon, &query, bbox.as_ref()) {
+            continue;
+        }
+        let entry = aircraft_entry(hex, a, &views);
+        let properties = serde_json::to_value(&entry).expect("aircraft entries always serialize");
+        features.push(Feature {
+            id: hex.clone(),
+            geometry: Geometry::Point { coordinates: [lon, lat] },
+            properties,
+        });
+
+        let trail: Vec<[f64; 2]> = a.trail.iter().map(|p| [p.longitude, p.latitude]).collect();
+        if query.trails && trail.len() >= 2 {
+            features.push(Feature {
+                id: format!("{}-trail", hex),
+                geometry: Geometry::LineString { coordinates: trail },
+                properties: serde_json::json!({
+                    "hex": entry.hex,
+                    "callsign": entry.callsign,
+                    "trail": true,
+                }),
+            });
+        }
+    }
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/geo+json")],
+        Json(FeatureCollection { features }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::app;
+    use super::super::tests::{make_aircraft, make_state, response_body};
+    use super::*;
+    use crate::trail::TrailPoint;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use tokio::time::Instant;
+    use tower::ServiceExt;
+
+    fn at(lat: f64, lon: f64, alt: f64) -> Aircraft {
+        let mut a = make_aircraft(Some("FLT1"));
+        a.latitude = Some(lat);
+        a.longitude = Some(lon);
+        a.altitude_ft = Some(alt);
+        a
+    }
+
+    async fn fetch(state: Arc<AppState>, uri: &str) -> (StatusCode, String) {
+        let response = app(state)
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        (response.status(), response_body(response).await)
+    }
+
+    #[test]
+    fn bbox_parses_four_ordered_values() {
+        assert_eq!(
+            Bbox::parse("-75,39.5,-73,41").unwrap(),
+            Bbox { min_lon: -75.0, min_lat: 39.5, max_lon: -73.0, max_lat: 41.0 }
+        );
+        assert!(Bbox::parse("-75,39.5,-73").is_err());
+        assert!(Bbox::parse("-75,41,-73,39.5").is_err());
+        assert!(Bbox::parse("a,b,c,d").is_err());
+    }
+
+    #[tokio::test]
+    async fn points_carry_aircraft_properties() {
+        let mut no_position = make_aircraft(Some("FLT2"));
+        no_position.latitude = None;
+        let state = make_state("FLT1", vec![("AA1111", at(40.0, -74.0, 5000.0)), ("BB2222", no_position)]);
+        let (status, body) = fetch(state, "/api/aircraft.geojson").await;
+
+        assert_eq!(status, 200);
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["type"], "FeatureCollection");
+        let collection: FeatureCollection = serde_json::from_value(value).unwrap();
+        assert_eq!(collection.features.len(), 1);
+        let feature = &collection.features[0];
+        assert_eq!(feature.geometry, Geometry::Point { coordinates: [-74.0, 40.0] });
+        assert_eq!(feature.properties["hex"], "AA1111");
+        assert_eq!(feature.properties["alt_ft"], 5000.0);
+        assert_eq!(feature.properties["tracking"], true);
+    }
+
+    #[tokio::test]
+    async fn trails_are_line_strings() {
+        let mut a = at(40.02, -74.0, 5000.0);
+        for lat in [40.0, 40.01, 40.02] {
+            a.trail.push(TrailPoint {
+                latitude: lat,
+                longitude: -74.0,
+                altitude_ft: Some(5000.0),
+                ground_speed_kt: None,
+                at: Instant::now(),
+            });
+        }
+        let state = make_state("FLT1", vec![("AA1111", a)]);
+        let (_, body) = fetch(state.clone(), "/api/aircraft.geojson").await;
+        let collection: FeatureCollection = serde_json::from_str(&body).unwrap();
+        assert_eq!(collection.features.len(), 1);
+
+        let (_, body) = fetch(state, "/api/aircraft.geojson?trails=true").await;
+        let collection: FeatureCollection = serde_json::from_str(&body).unwrap();
+        assert_eq!(collection.features.len(), 2);
+        assert_eq!(collection.features[1].id, "AA1111-trail");
+        assert_eq!(
+            collection.features[1].geometry,
+            Geometry::LineString { coordinates: vec![[-74.0, 40.0], [-74.0, 40.01], [-74.0, 40.02]] }
+        );
+    }
+
+    #[tokio::test]
+    async fn filters_by_bbox_and_altitude() {
+        let state = make_state("FLT1", vec![
+            ("AA1111", at(40.0, -74.0, 5000.0)),
+            ("BB2222", at(40.5, -74.0, 12000.0)),
+            ("CC3333", at(45.0, -74.0, 8000.0)),
+        ]);
+        let ids = |body: String| {
+            let collection: FeatureCollection = serde_json::from_str(&body).unwrap();
+            collection.features.into_iter().map(|f| f.id).collect::<Vec<_>>()
+        };
+
+        let (_, body) = fetch(state.clone(), "/api/aircraft.geojson?bbox=-75,39,-73,41").await;
+        assert_eq!(ids(body), vec!["AA1111", "BB2222"]);
+        let (_, body) = fetch(state.clone(), "/api/aircraft.geojson?min_alt=6000").await;
+        assert_eq!(ids(body), vec!["BB2222", "CC3333"]);
+        let (_, body) = fetch(state, "/api/aircraft.geojson?bbox=-75,39,-73,41&max_alt=10000").await;
+        assert_eq!(ids(body), vec!["AA1111"]);
+    }
+
+    #[tokio::test]
+    async fn bbox_can_cross_the_antimeridian() {
+        let bbox = Bbox::parse("170,-20,-170,-10").unwrap();
+        assert!(bbox.contains(-15.0, 175.0));
+        assert!(bbox.contains(-15.0, -175.0));
+        assert!(bbox.contains(-15.0, 180.0));
+        assert!(!bbox.contains(-15.0, 0.0));
+        assert!(!bbox.contains(-5.0, 175.0));
+
+        let state = make_state("FLT1", vec![
+            ("AA1111", at(-17.0, 178.5, 5000.0)),
+            ("BB2222", at(-14.0, -171.5, 5000.0)),
+            ("CC3333", at(-15.0, 150.0, 5000.0)),
+        ]);
+        let (status, body) = fetch(state, "/api/aircraft.geojson?bbox=170,-20,-170,-10").await;
+        assert_eq!(status, 200);
+        let collection: FeatureCollection = serde_json::from_str(&body).unwrap();
+        let ids: Vec<_> = collection.features.into_iter().map(|f| f.id).collect();
+        assert_eq!(ids, vec!["AA1111", "BB2222"]);
+    }
+
+    #[tokio::test]
+    async fn bad_filters_are_rejected() {
+        let state = make_state("FLT1", vec![]);
+        let (status, body) = fetch(state.clone(), "/api/aircraft.geojson?bbox=1,2,3").await;
+        assert_eq!(status, 400);
+        assert!(body.contains("bbox needs four values"));
+        let (status, _) = fetch(state, "/api/aircraft.geojson?min_alt=high").await;
+        assert_eq!(status, 400);
+    }
+}
@@ -0,0 +1,253 @@
+use super::{aircraft_entry, escape_html, view_slots, AppState, AircraftEntry};
+use crate::Aircraft;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, Uri};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use serde::Deserialize;
+use std::fmt::Write;
+use std::sync::Arc;
+
+const KML_TYPE: &str = "application/vnd.google-earth.kml+xml";
+const FEET_TO_METRES: f64 = 0.3048;
+/// Seconds between Google Earth polls of the live feed.
+const DEFAULT_REFRESH_SECS: u32 = 5;
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/kml/live.kml", get(get_live))
+        .route("/kml/link.kml", get(get_link))
+}
+
+#[derive(Debug, Deserialize)]
+struct LinkQuery {
+    refresh: Option<u32>,
+}
+
+/// Placemark for one aircraft: extruded to its altitude, icon turned to its
+/// track. Aircraft without an altitude sit on the ground.
+fn placemark(out: &mut String, entry: &AircraftEntry, lat: f64, lon: f64) {
+    let (style, colour) = if entry.tracking {
+        ("tracked", "ff66ff33")
+    } else {
+        ("other", "ffffd400")
+    };
+    let heading = entry.track.map_or(String::new(), |t| format!("<heading>{}</heading>", t));
+    let (point, alt) = match entry.alt_ft {
+        Some(alt) => (
+            format!(
+                "<extrude>1</extrude><altitudeMode>absolute</altitudeMode><coordinates>{},{},{:.0}</coordinates>",
+                lon,
+                lat,
+                alt * FEET_TO_METRES
+            ),
+            format!("{} ft", alt),
+        ),
+        None => (
+            format!("<altitudeMode>clampToGround</altitudeMode><coordinates>{},{}</coordinates>", lon, lat),
+            "no altitude".to_string(),
+        ),
+    };
+    let name = if entry.callsign.is_empty() { &entry.hex } else { &entry.callsign };
+    let _ = writeln!(
+        out,
+        "<Placemark id=\"{hex}\"><name>{name}</name><description>{hex} {reg} {type_code}, {alt}, {gs}</description>\
+<styleUrl>#{style}</styleUrl><Style><IconStyle><color>{colour}</color>{heading}\
+<Icon><href>http://maps.google.com/mapfiles/kml/shapes/track.png</href></Icon></IconStyle></Style>\
+<Point>{point}</Point></Placemark>",
+        hex = escape_html(&entry.hex),
+        name = escape_html(name),
+        reg = escape_html(entry.registration.as_deref().unwrap_or("-")),
+        type_code = escape_html(entry.type_code.as_deref().unwrap_or("-")),
+        gs = entry.gs_kt.map_or("no speed".to_string(), |gs| format!("{} kt", gs)),
+    );
+}
+
+/// The trail of a tracked aircraft as a 3D line; points heard without an
+/// altitude can't be placed and are left out.
+fn track_line(out: &mut String, entry: &AircraftEntry, a: &Aircraft) {
+    let coordinates: Vec<String> = a
+        .trail
+        .iter()
+        .filter_map(|p| {
+            p.altitude_ft
+                .map(|alt| format!("{},{},{:.0}", p.longitude, p.latitude, alt * FEET_TO_METRES))
+        })
+        .collect();
+    if coordinates.len() < 2 {
+        return;
+    }
+    let _ = writeln!(
+        out,
+        "<Placemark id=\"{hex}-track\"><name>{hex} track</name><styleUrl>#track</styleUrl>\
+<LineString><altitudeMode>absolute</altitudeMode><coordinates>{coordinates}</coordinates></LineString></Placemark>",
+        hex = escape_html(&entry.hex),
+        coordinates = coordinates.join(" "),
+    );
+}
+
+async fn live_kml(state: &AppState) -> String {
+    let map = state.aircraft_map.read().await;
+    let views = view_slots(&state.slots, &map).await;
+    let mut hexes: Vec<&String> = map.keys().collect();
+    hexes.sort();
+
+    let mut body = String::new();
+    for hex in hexes {
+        let a = &map[hex];
+        let (Some(lat), Some(lon)) = (a.latitude, a.longitude) else {
+            continue;
+        };
+        let entry = aircraft_entry(hex, a, &views);
+        placemark(&mut body, &entry, lat, lon);
+        if entry.tracking {
+            track_line(&mut body, &entry, a);
+        }
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2">
+<Document>
+<name>adsb_xgps live</name>
+<Style id="tracked"><IconStyle><scale>1.2</scale></IconStyle><LineStyle><color>ff66ff33</color></LineStyle></Style>
+<Style id="other"><IconStyle><scale>0.8</scale></IconStyle><LineStyle><color>ffffd400</color></LineStyle></Style>
+<Style id="track"><LineStyle><color>ff66ff33</color><width>3</width></LineStyle></Style>
+{body}</Document>
+</kml>
+"#
+    )
+}
+
+async fn get_live(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, KML_TYPE)], live_kml(&state).await)
+}
+
+/// A NetworkLink pointing back at the live feed on the host the client
+/// used. HTTP/2 clients, which HTTPS offers, name it in the URI instead.
+async fn get_link(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    uri: Uri,
+    Query(query): Query<LinkQuery>,
+) -> impl IntoResponse {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .or(uri.authority().map(|a| a.as_str()))
+        .unwrap_or("localhost:8081");
+    let refresh = query.refresh.unwrap_or(DEFAULT_REFRESH_SECS).max(1);
+    let kml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2">
+<NetworkLink>
+<name>adsb_xgps</name>
+<Link>
+<href>{scheme}://{host}/kml/live.kml</href>
+<refreshMode>onInterval</refreshMode>
+<refreshInterval>{refresh}</refreshInterval>
+</Link>
+</NetworkLink>
+</kml>
+"#,
+        scheme = if state.https { "https" } else { "http" },
+        host = escape_html(host),
+    );
+    (
+        [
+            (header::CONTENT_TYPE, KML_TYPE),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"adsb_xgps.kml\""),
+        ],
+        kml,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::app;
+    use super::super::tests::{make_aircraft, make_state, response_body};
+    use crate::trail::TrailPoint;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use std::sync::Arc;
+    use tokio::time::Instant;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn live_feed_has_extruded_placemarks_and_track() {
+        let mut tracked = make_aircraft(Some("FLT1"));
+        for (lat, alt) in [(39.9, Some(4000.0)), (39.95, None), (40.0, Some(5000.0))] {
+            tracked.trail.push(TrailPoint {
+                latitude: lat,
+                longitude: -74.0,
+                altitude_ft: alt,
+                ground_speed_kt: None,
+                at: Instant::now(),
+            });
+        }
+        let mut other = make_aircraft(Some("FLT2"));
+        other.altitude_ft = None;
+        other.track = None;
+        let state = make_state("FLT1", vec![("AA1111", tracked), ("BB2222", other)]);
+        let response = app(state)
+            .oneshot(Request::builder().uri("/kml/live.kml").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let content_type = response.headers().get("content-type").unwrap().to_str().unwrap();
+        assert_eq!(content_type, "application/vnd.google-earth.kml+xml");
+        let body = response_body(response).await;
+        assert!(body.contains("<Placemark id=\"AA1111\"><name>FLT1</name>"));
+        assert!(body.contains("<heading>270</heading>"));
+        assert!(body.contains(
+            "<extrude>1</extrude><altitudeMode>absolute</altitudeMode><coordinates>-74,40,10668</coordinates>"
+        ));
+        assert!(body.contains("<styleUrl>#tracked</styleUrl>"));
+        assert!(body.contains(
+            "<LineString><altitudeMode>absolute</altitudeMode><coordinates>-74,39.9,1219 -74,40,1524</coordinates>"
+        ));
+        assert!(body.contains("<Placemark id=\"BB2222\"><name>FLT2</name>"));
+        assert!(body.contains("<altitudeMode>clampToGround</altitudeMode>"));
+        assert!(!body.contains("BB2222-track"));
+    }
+
+    #[tokio::test]
+    async fn link_points_at_live_feed() {
+        let state = make_state("FLT1", vec![]);
+        let response = app(state)
+            .oneshot(
+                Request::builder()
+                    .uri("/kml/link.kml?refresh=10")
+                    .header("host", "radio.local:8081")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body = response_body(response).await;
+        assert!(body.contains("<href>http://radio.local:8081/kml/live.kml</href>"));
+        assert!(body.contains("<refreshInterval>10</refreshInterval>"));
+    }
+
+    #[tokio::test]
+    async fn link_over_https_uses_uri_authority() {
+        let mut state = Arc::try_unwrap(make_state("FLT1", vec![])).ok().unwrap();
+        state.https = true;
+        let response = app(Arc::new(state))
+            .oneshot(
+                Request::builder()
+                    .uri("https://radio.local:8443/kml/link.kml")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response_body(response).await;
+        assert!(body.contains("<href>https://radio.local:8443/kml/live.kml</href>"));
+    }
+}
@@ -0,0 +1,190 @@
+use super::{escape_html, AppState};
+use crate::recorder::is_recording;
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use std::path::Path as FsPath;
+use std::sync::Arc;
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/recordings", get(get_recordings))
+        .route("/recordings/{name}", get(get_recording))
+}
+
+/// Recordings in the directory as (name, size), newest first. Names start
+/// with their start time, so that's reverse name order.
+async fn list(dir: &FsPath) -> std::io::Result<Vec<(String, u64)>> {
+    let mut files = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let meta = entry.metadata().await?;
+        if meta.is_file() && is_recording(&name) {
+            files.push((name, meta.len()));
+        }
+    }
+    files.sort_by(|a, b| b.0.cmp(&a.0));
+    Ok(files)
+}
+
+fn not_recording() -> Response {
+    (StatusCode::NOT_FOUND, "Recording is off; start with --record DIR").into_response()
+}
+
+async fn get_recordings(State(state): State<Arc<AppState>>) -> Response {
+    let Some(dir) = &state.recordings else {
+        return not_recording();
+    };
+    let files = match list(dir).await {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Web: failed to list recordings in {}: {}", dir.display(), e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to list recordings").into_response();
+        }
+    };
+
+    let mut rows = String::new();
+    for (name, size) in &files {
+        rows.push_str(&format!(
+            "<tr><td><a href=\"/recordings/{name}\">{name}</a></td><td class=\"r\">{size}</td></tr>\n",
+            name = escape_html(name),
+        ));
+    }
+    if files.is_empty() {
+        rows.push_str("<tr><td colspan=\"2\">Nothing recorded yet</td></tr>\n");
+    }
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>adsb_xgps recordings</title>
+<style>
+body {{ font-family: monospace; background: #1a1a2e; color: #e0e0e0; margin: 20px; }}
+h1 {{ color: #00d4ff; }}
+a {{ color: #00d4ff; }}
+table {{ border-collapse: collapse; }}
+th, td {{ border: 1px solid #333; padding: 6px 10px; text-align: left; }}
+th {{ background: #16213e; color: #00d4ff; }}
+.r {{ text-align: right; }}
+</style>
+</head>
+<body>
+<h1>Recordings</h1>
+<p><a href="/">Back</a> &middot; {dir}</p>
+<table>
+<thead><tr><th>File</th><th>Bytes</th></tr></thead>
+<tbody>
+{rows}</tbody>
+</table>
+</body>
+</html>"#,
+        dir = escape_html(&dir.display().to_string()),
+    ))
+    .into_response()
+}
+
+async fn get_recording(State(state): State<Arc<AppState>>, Path(name): Path<String>) -> Response {
+    let Some(dir) = &state.recordings else {
+        return not_recording();
+    };
+    if !is_recording(&name) {
+        return (StatusCode::NOT_FOUND, "No such recording").into_response();
+    }
+    let content_type = match name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).as_deref() {
+        Some("gpx") => "application/gpx+xml",
+        Some("kml") => "application/vnd.google-earth.kml+xml",
+        _ => "text/csv",
+    };
+    match tokio::fs::read(dir.join(&name)).await {
+        Ok(bytes) => (
+            [
+                (header::CONTENT_TYPE, content_type.to_string()),
+                (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name)),
+            ],
+            bytes,
+        )
+            .into_response(),
+        Err(_) => (StatusCode::NOT_FOUND, "No such recording").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::app;
+    use super::super::tests::{make_state, response_body};
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use std::path::PathBuf;
+    use tower::ServiceExt;
+
+    fn with_dir(name: &str) -> (Arc<AppState>, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("adsb_xgps_web_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut state = Arc::try_unwrap(make_state("FLT1", vec![])).ok().unwrap();
+        state.recordings = Some(dir.clone());
+        (Arc::new(state), dir)
+    }
+
+    async fn get(state: Arc<AppState>, uri: &str) -> Response {
+        app(state)
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn lists_recordings_newest_first() {
+        let (state, dir) = with_dir("list");
+        std::fs::write(dir.join("19700101T000100Z-main-FLT1-AA1111.gpx"), "a").unwrap();
+        std::fs::write(dir.join("19700101T000200Z-main-FLT1-BB2222.csv"), "bb").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let body = response_body(get(state, "/recordings").await).await;
+        let first = body.find("000200Z-main-FLT1-BB2222.csv").unwrap();
+        let second = body.find("000100Z-main-FLT1-AA1111.gpx").unwrap();
+        assert!(first < second);
+        assert!(body.contains("<a href=\"/recordings/19700101T000200Z-main-FLT1-BB2222.csv\">"));
+        assert!(!body.contains("notes.txt"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn downloads_recording() {
+        let (state, dir) = with_dir("download");
+        std::fs::write(dir.join("19700101T000100Z-main-FLT1-AA1111.gpx"), "<gpx/>").unwrap();
+
+        let response = get(state.clone(), "/recordings/19700101T000100Z-main-FLT1-AA1111.gpx").await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "application/gpx+xml");
+        assert_eq!(
+            response.headers()["content-disposition"],
+            "attachment; filename=\"19700101T000100Z-main-FLT1-AA1111.gpx\""
+        );
+        assert_eq!(response_body(response).await, "<gpx/>");
+
+        assert_eq!(get(state.clone(), "/recordings/missing.gpx").await.status(), 404);
+        assert_eq!(get(state, "/recordings/..%2Fsecret.gpx").await.status(), 404);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn index_links_recordings() {
+        let (state, dir) = with_dir("index");
+        let body = response_body(get(state, "/").await).await;
+        assert!(body.contains("<a href=\"/recordings\">Recordings</a>"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn off_without_directory() {
+        let state = make_state("FLT1", vec![]);
+        assert!(!response_body(get(state.clone(), "/").await).await.contains("/recordings"));
+        assert_eq!(get(state, "/recordings").await.status(), 404);
+    }
+}
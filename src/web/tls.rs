@@ -0,0 +1,181 @@
+use super::WebBind;
+use axum_server::tls_rustls::RustlsConfig;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How often the certificate and key are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// PEM certificate chain and private key the web interface serves HTTPS with.
+#[derive(Clone, Debug)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Loaded certificate, shared by every listener and swapped in place when
+/// the files change.
+#[derive(Clone)]
+pub struct Tls {
+    files: TlsFiles,
+    config: RustlsConfig,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl TlsFiles {
+    /// Write a self-signed certificate for `names`, and its key, unless
+    /// both files are there already. True when they were written.
+    pub fn create_self_signed(&self, names: Vec<String>) -> Result<bool, String> {
+        match (self.cert.exists(), self.key.exists()) {
+            (true, true) => return Ok(false),
+            (false, false) => {}
+            (true, false) => return Err(format!("{} exists but {} doesn't", self.cert.display(), self.key.display())),
+            (false, true) => return Err(format!("{} exists but {} doesn't", self.key.display(), self.cert.display())),
+        }
+        let certified = rcgen::generate_simple_self_signed(names)
+            .map_err(|e| format!("cannot generate a certificate: {}", e))?;
+        std::fs::write(&self.cert, certified.cert.pem())
+            .map_err(|e| format!("cannot write {}: {}", self.cert.display(), e))?;
+        write_private(&self.key, &certified.key_pair.serialize_pem())
+            .map_err(|e| format!("cannot write {}: {}", self.key.display(), e))?;
+        Ok(true)
+    }
+
+    pub async fn load(&self) -> Result<Tls, String> {
+        let config = RustlsConfig::from_pem_file(&self.cert, &self.key)
+            .await
+            .map_err(|e| self.error(e))?;
+        Ok(Tls {
+            files: self.clone(),
+            config,
+        })
+    }
+
+    fn error(&self, e: std::io::Error) -> String {
+        format!(
+            "cannot load certificate {} with key {}: {}",
+            self.cert.display(),
+            self.key.display(),
+            e
+        )
+    }
+
+    fn modified(&self) -> (Option<SystemTime>, Option<SystemTime>) {
+        (modified(&self.cert), modified(&self.key))
+    }
+}
+
+/// Keys are only for this user to read.
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    std::fs::write(path, contents)
+}
+
+/// Names a self-signed certificate should cover: this machine, plus any
+/// host or address the interface was bound to by name.
+pub fn self_signed_names(binds: &[WebBind]) -> Vec<String> {
+    let mut names: Vec<String> = ["localhost", "127.0.0.1", "::1"].iter().map(|s| s.to_string()).collect();
+    for bind in binds {
+        let Some((host, _)) = bind.spec.rsplit_once(':') else {
+            continue;
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.parse::<IpAddr>().is_ok_and(|ip| ip.is_unspecified()) {
+            continue;
+        }
+        if !names.iter().any(|n| n.eq_ignore_ascii_case(host)) {
+            names.push(host.to_string());
+        }
+    }
+    names
+}
+
+impl Tls {
+    pub(super) fn config(&self) -> RustlsConfig {
+        self.config.clone()
+    }
+
+    /// Reload the certificate whenever either file changes, so a renewed
+    /// one is picked up without a restart. A failed reload, as when only
+    /// one file has been replaced so far, keeps the current certificate.
+    pub(super) async fn reload_on_change(self) {
+        let mut last = self.files.modified();
+        loop {
+            tokio::time::sleep(RELOAD_INTERVAL).await;
+            let now = self.files.modified();
+            if now == last {
+                continue;
+            }
+            last = now;
+            match self.config.reload_from_pem_file(&self.files.cert, &self.files.key).await {
+                Ok(()) => println!("Web: reloaded certificate from {}", self.files.cert.display()),
+                Err(e) => eprintln!("Web: keeping the current certificate; {}", self.files.error(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_bind;
+    use super::*;
+
+    fn files(name: &str) -> TlsFiles {
+        let dir = std::env::temp_dir().join(format!("adsb_xgps_tls_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TlsFiles {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+        }
+    }
+
+    #[test]
+    fn names_cover_bound_hosts() {
+        let binds: Vec<WebBind> = ["8081", "0.0.0.0:8081", "[::]:8082", "127.0.0.1:8083", "192.168.1.20:8084"]
+            .iter()
+            .map(|s| parse_bind(s).unwrap())
+            .collect();
+        assert_eq!(self_signed_names(&binds), vec!["localhost", "127.0.0.1", "::1", "192.168.1.20"]);
+    }
+
+    #[tokio::test]
+    async fn self_signed_certificate_loads_once_written() {
+        let files = files("self_signed");
+        assert!(files.load().await.is_err());
+        assert!(files.create_self_signed(vec!["localhost".to_string()]).unwrap());
+        let cert = std::fs::read_to_string(&files.cert).unwrap();
+        assert!(cert.starts_with("-----BEGIN CERTIFICATE-----"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&files.key).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert!(files.load().await.is_ok());
+
+        // Left alone on later runs
+        assert!(!files.create_self_signed(vec!["localhost".to_string()]).unwrap());
+        assert_eq!(std::fs::read_to_string(&files.cert).unwrap(), cert);
+
+        std::fs::remove_file(&files.key).unwrap();
+        assert!(files.create_self_signed(vec!["localhost".to_string()]).is_err());
+        std::fs::remove_dir_all(files.cert.parent().unwrap()).unwrap();
+    }
+}
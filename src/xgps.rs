@@ -0,0 +1,568 @@
+use crate::attitude::{Attitude, AttitudeEstimator};
+use crate::recorder::Recorder;
+use crate::tracker::{self, Slots};
+use crate::{AircraftMap, Fix};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::net::UdpSocket;
+use tokio::sync::{watch, RwLock};
+use tokio::time::{self, Duration, Instant};
+
+pub const DEFAULT_PORT: u16 = 49002;
+pub const DEFAULT_SIM_NAME: &str = "adsb_xgps";
+
+/// Dialect of the XGPS sentence a destination expects. There is no
+/// comma-less variant: the XGPS and XATT sentences are comma separated in
+/// every spec we could find, and without one to test against a guessed
+/// format would only look supported.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Variant {
+    /// `XGPS<name>,lon,lat,alt_m,track,gs_ms`
+    #[default]
+    Standard,
+    /// Standard XGPS plus ForeFlight's `XATT<name>,heading,pitch,roll`
+    ForeFlight,
+}
+
+impl Variant {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "standard",
+            Variant::ForeFlight => "foreflight",
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "standard" | "xgps" => Ok(Variant::Standard),
+            "foreflight" => Ok(Variant::ForeFlight),
+            "legacy" | "commaless" | "comma-less" => Err(format!(
+                "XGPS variant '{}' is not supported: no documented comma-less format exists (expected standard or foreflight)",
+                s
+            )),
+            _ => Err(format!(
+                "unknown XGPS variant '{}' (expected standard or foreflight)",
+                s
+            )),
+        }
+    }
+}
+
+/// One XGPS receiver, with counters of the datagrams sent to it.
+#[derive(Clone, Debug)]
+pub struct Destination {
+    pub addr: SocketAddr,
+    pub variant: Variant,
+    pub sent: u64,
+    pub errors: u64,
+    /// Tracking slot feeding this destination; `None` is the primary slot
+    pub slot: Option<String>,
+    /// Set for destinations added by EFB discovery rather than configured
+    pub discovery: Option<Discovery>,
+}
+
+/// How a discovered destination announced itself.
+#[derive(Clone, Debug)]
+pub struct Discovery {
+    pub app: String,
+    pub last_seen: Instant,
+}
+
+pub type Destinations = Arc<RwLock<Vec<Destination>>>;
+
+impl Destination {
+    pub fn new(addr: SocketAddr, variant: Variant) -> Self {
+        Destination {
+            addr,
+            variant,
+            sent: 0,
+            errors: 0,
+            slot: None,
+            discovery: None,
+        }
+    }
+
+    /// Best guess at how the address is reached. Directed broadcasts can't be
+    /// told apart from hosts without the netmask, so x.x.x.255 counts as one.
+    pub fn kind(&self) -> &'static str {
+        match self.addr.ip() {
+            IpAddr::V4(ip) if ip.is_multicast() => "multicast",
+            IpAddr::V4(ip) if ip.is_broadcast() || ip.octets()[3] == 255 => "broadcast",
+            _ => "unicast",
+        }
+    }
+}
+
+/// Parse `[SLOT=]HOST[:PORT][@VARIANT]` into a destination, defaulting to
+/// the primary slot and the standard variant.
+pub fn parse_destination(s: &str) -> Result<Destination, String> {
+    let (slot, s) = match s.trim().split_once('=') {
+        Some((slot, rest)) => (Some(tracker::parse_slot_name(slot)?), rest),
+        None => (None, s),
+    };
+    let (addr, variant) = match s.trim().rsplit_once('@') {
+        Some((addr, variant)) => (addr, variant.parse()?),
+        None => (s, Variant::default()),
+    };
+    let mut dest = Destination::new(parse_address(addr)?, variant);
+    dest.slot = slot;
+    Ok(dest)
+}
+
+/// Parse `HOST[:PORT]` into an IPv4 socket address, defaulting to the XGPS
+/// port and resolving hostnames.
+pub fn parse_address(s: &str) -> Result<SocketAddr, String> {
+    let s = s.trim();
+    let (host, port) = match s.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => {
+            let port = port
+                .parse()
+                .map_err(|_| format!("invalid port '{}'", port))?;
+            (host, port)
+        }
+        _ => (s, DEFAULT_PORT),
+    };
+
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return match ip {
+            IpAddr::V4(_) => Ok(SocketAddr::new(ip, port)),
+            IpAddr::V6(_) => Err("only IPv4 destinations are supported".to_string()),
+        };
+    }
+
+    (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve '{}': {}", host, e))?
+        .find(SocketAddr::is_ipv4)
+        .ok_or_else(|| format!("'{}' has no IPv4 address", host))
+}
+
+/// Sentences for one fix in the given variant, each sent as its own datagram.
+pub fn format_sentences(
+    fix: &Fix,
+    attitude: &Attitude,
+    variant: Variant,
+    sim_name: &str,
+) -> Vec<String> {
+    let Fix {
+        longitude: lon,
+        latitude: lat,
+        altitude_ft: alt_ft,
+        track,
+        ground_speed_kt: gs_kt,
+        ..
+    } = *fix;
+
+    let alt_m = alt_ft * 0.3048;
+    let gs_ms = gs_kt * 0.514444;
+
+    match variant {
+        Variant::Standard => {
+            vec![format!("XGPS{sim_name},{lon},{lat},{alt_m:.1},{track:.2},{gs_ms:.1}")]
+        }
+        Variant::ForeFlight => vec![
+            format!("XGPS{sim_name},{lon:.4},{lat:.4},{alt_m:.1},{track:.2},{gs_ms:.1}"),
+            format!(
+                "XATT{sim_name},{track:.1},{:.1},{:.1}",
+                attitude.pitch_deg, attitude.roll_deg
+            ),
+        ],
+    }
+}
+
+/// The sim name follows `XGPS` directly and ends at the first comma, so it
+/// must not contain one.
+pub fn parse_sim_name(s: &str) -> Result<String, String> {
+    if s.is_empty() {
+        Err("sim name must not be empty".to_string())
+    } else if s.contains(',') || s.contains('\n') {
+        Err("sim name must not contain commas or newlines".to_string())
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+/// The primary slot's fix and attitude, published by the broadcaster once a
+/// tick for the simulator outputs.
+pub type MainFix = watch::Receiver<Option<(Fix, Attitude)>>;
+
+/// Advance every slot's tracker once a second, send each destination its
+/// slot's fix, and publish the primary slot's fix to `main_fix`.
+pub async fn broadcaster(
+    slots: Slots,
+    aircraft_map: AircraftMap,
+    destinations: Destinations,
+    sim_name: String,
+    multicast_ttl: u32,
+    mut recorder: Option<Recorder>,
+    main_fix: watch::Sender<Option<(Fix, Attitude)>>,
+) {
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .await
+        .expect("Failed to bind UDP socket");
+    socket
+        .set_broadcast(true)
+        .expect("Failed to enable broadcast");
+    socket
+        .set_multicast_ttl_v4(multicast_ttl)
+        .expect("Failed to set multicast TTL");
+
+    for dest in destinations.read().await.iter() {
+        println!(
+            "Sending XGPS to {} ({}, {} variant) from slot {}",
+            dest.addr,
+            dest.kind(),
+            dest.variant,
+            dest.slot.as_deref().unwrap_or(&slots[0].name)
+        );
+    }
+
+    let mut estimators: Vec<AttitudeEstimator> = slots.iter().map(|_| AttitudeEstimator::default()).collect();
+    let mut interval = time::interval(Duration::from_secs(1));
+
+    loop {
+        interval.tick().await;
+
+        let mut fixes = Vec::with_capacity(slots.len());
+        // Target and aircraft of each slot, naming its recording
+        let mut followed = Vec::with_capacity(slots.len());
+        {
+            let map = aircraft_map.read().await;
+            for slot in slots.iter() {
+                let mut tracker = slot.tracker.write().await;
+                fixes.push(tracker.update(&map));
+                followed.push((tracker.target.to_string(), tracker.current.clone()));
+            }
+        }
+        let now = Instant::now();
+        let states: Vec<Option<(Fix, Attitude)>> = fixes
+            .into_iter()
+            .zip(estimators.iter_mut())
+            .map(|(fix, estimator)| fix.map(|fix| (fix, estimator.update(&fix, now))))
+            .collect();
+        main_fix.send_replace(states[0]);
+
+        // Send from a snapshot, so the web interface and discovery aren't
+        // kept waiting on the sends, then add up the counts afterwards
+        let targets: Vec<(SocketAddr, Variant, Option<String>)> = destinations
+            .read()
+            .await
+            .iter()
+            .map(|d| (d.addr, d.variant, d.slot.clone()))
+            .collect();
+        let mut sentences: HashMap<(usize, Variant), Vec<String>> = HashMap::new();
+        let mut results = Vec::with_capacity(targets.len());
+        for (addr, variant, slot) in targets {
+            let Some(index) = tracker::slot_index(&slots, slot.as_deref()) else {
+                continue;
+            };
+            let Some((fix, attitude)) = &states[index] else {
+                continue;
+            };
+            let msgs = sentences
+                .entry((index, variant))
+                .or_insert_with(|| format_sentences(fix, attitude, variant, &sim_name));
+            let (mut sent, mut errors, mut last_error) = (0, 0, None);
+            for msg in msgs.iter() {
+                match socket.send_to(msg.as_bytes(), addr).await {
+                    Ok(_) => sent += 1,
+                    Err(e) => {
+                        errors += 1;
+                        last_error = Some(e);
+                    }
+                }
+            }
+            results.push((addr, variant, slot, sent, errors, last_error));
+        }
+        {
+            let mut destinations = destinations.write().await;
+            for (addr, variant, slot, sent, errors, last_error) in results {
+                // Discovery may have dropped it while we were sending
+                let Some(dest) = destinations
+                    .iter_mut()
+                    .find(|d| d.addr == addr && d.variant == variant && d.slot == slot)
+                else {
+                    continue;
+                };
+                dest.sent += sent;
+                dest.errors += errors;
+                if let Some(e) = last_error {
+                    eprintln!("UDP send error to {}: {} ({} errors)", addr, e, dest.errors);
+                }
+            }
+        }
+        for ((index, _), msgs) in &sentences {
+            for msg in msgs {
+                if slots.len() > 1 {
+                    println!("[{}] {}", slots[*index].name, msg);
+                } else {
+                    println!("{}", msg);
+                }
+            }
+        }
+
+        // Record each slot that was sent, once however many variants it went out as
+        if let Some(recorder) = recorder.as_mut() {
+            let time = SystemTime::now();
+            let mut sent: Vec<usize> = sentences.keys().map(|(index, _)| *index).collect();
+            sent.sort_unstable();
+            sent.dedup();
+            for index in sent {
+                let Some((fix, attitude)) = &states[index] else {
+                    continue;
+                };
+                let (target, hex) = &followed[index];
+                recorder.record(&slots[index].name, target, hex.as_deref(), fix, attitude, time);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_fix() -> Fix {
+        Fix {
+            longitude: -80.11,
+            latitude: 34.55,
+            altitude_ft: 3937.0,
+            track: 359.05,
+            ground_speed_kt: 108.089,
+            vertical_rate_fpm: None,
+        }
+    }
+
+    fn sample_attitude() -> Attitude {
+        Attitude {
+            pitch_deg: 3.25,
+            roll_deg: -12.5,
+        }
+    }
+
+    /// Strip `prefix` and parse the remaining comma separated numbers.
+    fn parse_fields(msg: &str, prefix: &str) -> Vec<f64> {
+        msg.strip_prefix(prefix)
+            .unwrap()
+            .split(',')
+            .map(|p| p.parse().unwrap())
+            .collect()
+    }
+
+    fn assert_xgps_fields(parts: &[f64]) {
+        assert_eq!(parts.len(), 5);
+        assert!((parts[0] - (-80.11)).abs() < 0.01);
+        assert!((parts[1] - 34.55).abs() < 0.01);
+        assert!((parts[2] - 1200.1).abs() < 0.2);
+        assert!((parts[3] - 359.05).abs() < 0.01);
+        assert!((parts[4] - 55.6).abs() < 0.2);
+    }
+
+    #[test]
+    fn xgps_format_string() {
+        let msgs = format_sentences(
+            &sample_fix(),
+            &sample_attitude(),
+            Variant::Standard,
+            DEFAULT_SIM_NAME,
+        );
+        assert_eq!(msgs.len(), 1);
+        assert!(msgs[0].starts_with("XGPSadsb_xgps,"));
+        assert_xgps_fields(&parse_fields(&msgs[0], "XGPSadsb_xgps,"));
+    }
+
+    #[test]
+    fn xgps_custom_sim_name() {
+        let msgs = format_sentences(&sample_fix(), &sample_attitude(), Variant::Standard, "My Sim");
+        assert!(msgs[0].starts_with("XGPSMy Sim,"));
+        assert_xgps_fields(&parse_fields(&msgs[0], "XGPSMy Sim,"));
+    }
+
+    #[test]
+    fn foreflight_format_strings() {
+        let msgs = format_sentences(
+            &sample_fix(),
+            &sample_attitude(),
+            Variant::ForeFlight,
+            "Hangar",
+        );
+        assert_eq!(msgs.len(), 2);
+        assert_xgps_fields(&parse_fields(&msgs[0], "XGPSHangar,"));
+
+        let att = parse_fields(&msgs[1], "XATTHangar,");
+        assert_eq!(att.len(), 3);
+        assert!((att[0] - 359.05).abs() < 0.1);
+        assert!((att[1] - 3.25).abs() < 0.1);
+        assert!((att[2] - (-12.5)).abs() < 0.1);
+    }
+
+    #[test]
+    fn sim_name_validation() {
+        assert_eq!(parse_sim_name("My Sim").unwrap(), "My Sim");
+        assert!(parse_sim_name("").is_err());
+        assert!(parse_sim_name("a,b").is_err());
+    }
+
+    #[test]
+    fn variant_from_str() {
+        assert_eq!("standard".parse::<Variant>().unwrap(), Variant::Standard);
+        assert_eq!("ForeFlight".parse::<Variant>().unwrap(), Variant::ForeFlight);
+        let err = "legacy".parse::<Variant>().unwrap_err();
+        assert!(err.contains("not supported"), "{}", err);
+        assert!("garmin".parse::<Variant>().is_err());
+    }
+
+    // --- Destination parsing ---
+
+    #[test]
+    fn destination_default_port() {
+        let dest = parse_destination("192.168.1.255").unwrap();
+        assert_eq!(dest.addr, "192.168.1.255:49002".parse().unwrap());
+        assert_eq!(dest.variant, Variant::Standard);
+    }
+
+    #[test]
+    fn destination_explicit_port() {
+        let dest = parse_destination("10.8.0.5:4000").unwrap();
+        assert_eq!(dest.addr, "10.8.0.5:4000".parse().unwrap());
+    }
+
+    #[test]
+    fn destination_with_variant() {
+        let dest = parse_destination("10.8.0.5@foreflight").unwrap();
+        assert_eq!(dest.addr, "10.8.0.5:49002".parse().unwrap());
+        assert_eq!(dest.variant, Variant::ForeFlight);
+
+        let dest = parse_destination("10.8.0.5:4000@standard").unwrap();
+        assert_eq!(dest.addr, "10.8.0.5:4000".parse().unwrap());
+        assert_eq!(dest.variant, Variant::Standard);
+
+        assert!(parse_destination("10.8.0.5@garmin").is_err());
+    }
+
+    #[test]
+    fn destination_with_slot() {
+        let dest = parse_destination("lab2=10.8.0.5:4000@foreflight").unwrap();
+        assert_eq!(dest.slot.as_deref(), Some("lab2"));
+        assert_eq!(dest.addr, "10.8.0.5:4000".parse().unwrap());
+        assert_eq!(dest.variant, Variant::ForeFlight);
+
+        assert!(parse_destination("10.8.0.5").unwrap().slot.is_none());
+        assert!(parse_destination("lab 2=10.8.0.5").is_err());
+    }
+
+    #[test]
+    fn destination_invalid_port() {
+        assert!(parse_destination("10.8.0.5:notaport").is_err());
+        assert!(parse_destination("10.8.0.5:70000").is_err());
+    }
+
+    #[test]
+    fn destination_ipv6_rejected() {
+        assert!(parse_destination("::1").is_err());
+        assert!(parse_destination("fe80::1").is_err());
+    }
+
+    #[test]
+    fn destination_kinds() {
+        let kind = |s| parse_destination(s).unwrap().kind();
+        assert_eq!(kind("255.255.255.255"), "broadcast");
+        assert_eq!(kind("192.168.1.255"), "broadcast");
+        assert_eq!(kind("239.1.2.3"), "multicast");
+        assert_eq!(kind("10.8.0.5"), "unicast");
+    }
+
+    /// Slots following FLT1, and a map with it in.
+    fn flt1() -> (Slots, AircraftMap) {
+        let aircraft = crate::Aircraft {
+            callsign: Some("FLT1".to_string()),
+            registration: None,
+            type_code: None,
+            squawk: None,
+            latitude: Some(40.0),
+            longitude: Some(-74.0),
+            altitude_ft: Some(5000.0),
+            ground_speed_kt: Some(250.0),
+            track: Some(90.0),
+            vertical_rate_fpm: None,
+            on_ground: None,
+            trail: crate::trail::Trail::default(),
+            updated: crate::FieldTimes::default(),
+            messages: [0; 8],
+            last_updated: Instant::now(),
+        };
+        let map: AircraftMap = Arc::new(RwLock::new(HashMap::from([("AA1111".to_string(), aircraft)])));
+        let slots: Slots = Arc::new(vec![tracker::Slot {
+            name: "main".to_string(),
+            tracker: Arc::new(RwLock::new(tracker::Tracker::new("FLT1".parse().unwrap()))),
+        }]);
+        (slots, map)
+    }
+
+    #[tokio::test]
+    async fn broadcaster_publishes_main_fix() {
+        let (slots, map) = flt1();
+        let (main_fix, mut main_fixes) = watch::channel(None);
+        let task = tokio::spawn(broadcaster(
+            slots.clone(),
+            map,
+            Arc::new(RwLock::new(Vec::new())),
+            DEFAULT_SIM_NAME.to_string(),
+            1,
+            None,
+            main_fix,
+        ));
+
+        main_fixes.changed().await.unwrap();
+        let (fix, _) = main_fixes.borrow_and_update().unwrap();
+        assert_eq!(fix.latitude, 40.0);
+        assert_eq!(fix.altitude_ft, 5000.0);
+        assert_eq!(slots[0].tracker.read().await.current.as_deref(), Some("AA1111"));
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn broadcaster_counts_sends() {
+        let (slots, map) = flt1();
+        let efb = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let dest = Destination::new(efb.local_addr().unwrap(), Variant::ForeFlight);
+        let destinations: Destinations = Arc::new(RwLock::new(vec![dest]));
+        let (main_fix, mut main_fixes) = watch::channel(None);
+        let task = tokio::spawn(broadcaster(
+            slots,
+            map,
+            destinations.clone(),
+            "Hangar".to_string(),
+            1,
+            None,
+            main_fix,
+        ));
+
+        let mut buf = [0u8; 256];
+        let len = efb.recv(&mut buf).await.unwrap();
+        assert!(buf[..len].starts_with(b"XGPSHangar,"));
+        let len = efb.recv(&mut buf).await.unwrap();
+        assert!(buf[..len].starts_with(b"XATTHangar,"));
+        // Counts are added once the tick's sends are done, before publishing the next
+        main_fixes.changed().await.unwrap();
+        main_fixes.changed().await.unwrap();
+        let dests = destinations.read().await;
+        assert!(dests[0].sent >= 2);
+        assert_eq!(dests[0].errors, 0);
+        task.abort();
+    }
+}
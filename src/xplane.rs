@@ -0,0 +1,88 @@
+use crate::attitude::Attitude;
+use crate::xgps::MainFix;
+use crate::Fix;
+use tokio::net::UdpSocket;
+
+/// Build an X-Plane `VEHX` packet placing aircraft `index` (0 is the user
+/// aircraft) at the given fix. Layout is the 5 byte header followed by a
+/// little-endian i32 index, f64 lat/lon/elevation (m MSL) and f32 true
+/// heading, pitch and roll in degrees.
+pub fn vehx_packet(index: i32, fix: &Fix, attitude: &Attitude) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(45);
+    buf.extend_from_slice(b"VEHX\0");
+    buf.extend_from_slice(&index.to_le_bytes());
+    buf.extend_from_slice(&fix.latitude.to_le_bytes());
+    buf.extend_from_slice(&fix.longitude.to_le_bytes());
+    buf.extend_from_slice(&(fix.altitude_ft * 0.3048).to_le_bytes());
+    buf.extend_from_slice(&(fix.track as f32).to_le_bytes());
+    buf.extend_from_slice(&(attitude.pitch_deg as f32).to_le_bytes());
+    buf.extend_from_slice(&(attitude.roll_deg as f32).to_le_bytes());
+    buf
+}
+
+/// Send each fix the broadcaster publishes for the primary slot, so the
+/// tracker is only advanced in one place.
+pub async fn sender(mut fixes: MainFix, host: String, port: u16) {
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .await
+        .expect("Failed to bind UDP socket");
+    let addr = format!("{}:{}", host, port);
+    println!("Sending X-Plane VEHX packets to {}", addr);
+
+    while fixes.changed().await.is_ok() {
+        let Some((fix, attitude)) = *fixes.borrow_and_update() else {
+            continue;
+        };
+        let packet = vehx_packet(0, &fix, &attitude);
+        if let Err(e) = socket.send_to(&packet, &addr).await {
+            eprintln!("X-Plane UDP send error: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vehx_packet_layout() {
+        let fix = Fix {
+            latitude: 34.55,
+            longitude: -80.11,
+            altitude_ft: 10000.0,
+            track: 359.05,
+            ground_speed_kt: 108.0,
+            vertical_rate_fpm: None,
+        };
+        let attitude = Attitude {
+            pitch_deg: 2.5,
+            roll_deg: -10.0,
+        };
+        let buf = vehx_packet(0, &fix, &attitude);
+
+        assert_eq!(buf.len(), 45);
+        assert_eq!(&buf[0..5], b"VEHX\0");
+        assert_eq!(i32::from_le_bytes(buf[5..9].try_into().unwrap()), 0);
+        assert_eq!(f64::from_le_bytes(buf[9..17].try_into().unwrap()), 34.55);
+        assert_eq!(f64::from_le_bytes(buf[17..25].try_into().unwrap()), -80.11);
+        let ele = f64::from_le_bytes(buf[25..33].try_into().unwrap());
+        assert!((ele - 3048.0).abs() < 0.01);
+        assert_eq!(f32::from_le_bytes(buf[33..37].try_into().unwrap()), 359.05);
+        assert_eq!(f32::from_le_bytes(buf[37..41].try_into().unwrap()), 2.5);
+        assert_eq!(f32::from_le_bytes(buf[41..45].try_into().unwrap()), -10.0);
+    }
+
+    #[test]
+    fn vehx_packet_aircraft_index() {
+        let fix = Fix {
+            latitude: 0.0,
+            longitude: 0.0,
+            altitude_ft: 0.0,
+            track: 0.0,
+            ground_speed_kt: 0.0,
+            vertical_rate_fpm: None,
+        };
+        let buf = vehx_packet(3, &fix, &Attitude::default());
+        assert_eq!(i32::from_le_bytes(buf[5..9].try_into().unwrap()), 3);
+    }
+}